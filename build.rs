@@ -0,0 +1,38 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// 嵌入内核镜像的用户程序,在user目录下
+const USER_PROGRAMS: [&str; 1] = ["hello"];
+
+/// 执行外部命令,失败的时候终止构建
+fn run(command: &mut Command) {
+    let status = command
+        .status()
+        .unwrap_or_else(|err| panic!("failed to run {:?}: {}", command, err));
+    assert!(status.success(), "{:?} exited with {}", command, status);
+}
+
+/// 用nasm编译用户程序,静态链接成ELF32,输出到OUT_DIR
+/// 内核用include_bytes!从OUT_DIR中嵌入
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    for name in USER_PROGRAMS {
+        let source = format!("user/{}.asm", name);
+        let object = Path::new(&out_dir).join(format!("{}.o", name));
+        let program = Path::new(&out_dir).join(name);
+
+        run(Command::new("nasm")
+            .args(["-f", "elf32", &source, "-o"])
+            .arg(&object));
+        run(Command::new("ld")
+            .args(["-m", "elf_i386", "-static"])
+            .arg(&object)
+            .arg("-o")
+            .arg(&program));
+
+        println!("cargo:rerun-if-changed={}", source);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -5,8 +5,10 @@ pub mod vga_driver;
 const BUFFER_WIDTH: usize = 80;
 /// vga text mode buffer height
 const BUFFER_HEIGHT: usize = 25;
+use crate::mm::page::KERNEL_BASE;
+
 /// vga buffer memory address
-const VGA_BUFFER_ADDR: usize = 0xb8000;
+const VGA_BUFFER_ADDR: usize = KERNEL_BASE as usize + 0xb8000;
 const VGA_INDEX_REGISTER: u16 = 0x3D4;
 const VGA_DATA_REGISTER: u16 = 0x3D5;
 
@@ -0,0 +1,202 @@
+//! IDE硬盘驱动,只支持主通道的主盘,使用LBA28和PIO轮询的方式读写扇区
+use x86::io::{inb, inw, outb, outw};
+
+use crate::kernel::sync::mutex::Mutex;
+
+/// 扇区的大小
+pub const SECTOR_SIZE: usize = 512;
+
+/// 主通道的端口
+const IDE_IOBASE: u16 = 0x1F0;
+const IDE_DATA: u16 = IDE_IOBASE;
+const IDE_SECTOR_COUNT: u16 = IDE_IOBASE + 2;
+const IDE_LBA_LOW: u16 = IDE_IOBASE + 3;
+const IDE_LBA_MID: u16 = IDE_IOBASE + 4;
+const IDE_LBA_HIGH: u16 = IDE_IOBASE + 5;
+const IDE_DEVICE: u16 = IDE_IOBASE + 6;
+const IDE_STATUS: u16 = IDE_IOBASE + 7;
+const IDE_COMMAND: u16 = IDE_IOBASE + 7;
+/// 控制寄存器,设置nIEN关闭硬盘中断
+const IDE_CONTROL: u16 = 0x3F6;
+const IDE_CONTROL_NIEN: u8 = 0x02;
+
+/// 状态寄存器
+const IDE_SR_ERR: u8 = 0x01;
+const IDE_SR_DRQ: u8 = 0x08;
+const IDE_SR_DF: u8 = 0x20;
+const IDE_SR_BSY: u8 = 0x80;
+/// 没有接硬盘的时候总线浮空,读出来全是1
+const IDE_SR_NONE: u8 = 0xFF;
+
+/// 命令
+const IDE_CMD_READ: u8 = 0x20;
+const IDE_CMD_WRITE: u8 = 0x30;
+const IDE_CMD_FLUSH: u8 = 0xE7;
+const IDE_CMD_IDENTIFY: u8 = 0xEC;
+
+/// 主盘,LBA模式
+const IDE_DEVICE_MASTER_LBA: u8 = 0xE0;
+/// LBA28一次最多读写256个扇区,扇区数写0表示256
+const IDE_MAX_SECTORS: usize = 256;
+/// 轮询状态的最大次数,超过认为硬盘没有响应
+const IDE_TIMEOUT: usize = 0x100000;
+
+/// 硬盘的信息,没有硬盘的时候为None
+static IDE_DISK: Mutex<Option<IdeDisk>> = Mutex::new(None);
+
+/// 主通道主盘
+#[derive(Copy, Clone, Debug)]
+struct IdeDisk {
+    /// LBA28可以访问的扇区数
+    sectors: u32,
+}
+
+impl IdeDisk {
+    /// 等待硬盘不忙,drq为true时还要等待数据准备好
+    fn wait(drq: bool) -> bool {
+        for _ in 0..IDE_TIMEOUT {
+            let status = unsafe { inb(IDE_STATUS) };
+            if status == IDE_SR_NONE {
+                return false;
+            }
+            if status & IDE_SR_BSY != 0 {
+                continue;
+            }
+            if status & (IDE_SR_ERR | IDE_SR_DF) != 0 {
+                return false;
+            }
+            if !drq || status & IDE_SR_DRQ != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 选择扇区并发送命令
+    fn command(lba: u32, count: usize, command: u8) -> bool {
+        if !Self::wait(false) {
+            return false;
+        }
+
+        unsafe {
+            outb(
+                IDE_DEVICE,
+                IDE_DEVICE_MASTER_LBA | ((lba >> 24) as u8 & 0xF),
+            );
+            // 扇区数是256的时候写入0
+            outb(IDE_SECTOR_COUNT, count as u8);
+            outb(IDE_LBA_LOW, lba as u8);
+            outb(IDE_LBA_MID, (lba >> 8) as u8);
+            outb(IDE_LBA_HIGH, (lba >> 16) as u8);
+            outb(IDE_COMMAND, command);
+        }
+        true
+    }
+
+    /// 从数据端口读出一个扇区
+    fn read_sector(buf: &mut [u8]) -> bool {
+        if !Self::wait(true) {
+            return false;
+        }
+
+        buf.chunks_exact_mut(2).for_each(|word| {
+            word.copy_from_slice(&unsafe { inw(IDE_DATA) }.to_le_bytes())
+        });
+        true
+    }
+
+    /// 向数据端口写入一个扇区
+    fn write_sector(buf: &[u8]) -> bool {
+        if !Self::wait(true) {
+            return false;
+        }
+
+        buf.chunks_exact(2).for_each(|word| unsafe {
+            outw(IDE_DATA, u16::from_le_bytes([word[0], word[1]]))
+        });
+        true
+    }
+
+    /// 检查扇区范围,长度必须是扇区的整数倍
+    fn check_range(&self, lba: u32, len: usize) -> bool {
+        len & (SECTOR_SIZE - 1) == 0
+            && (lba as u64 + (len / SECTOR_SIZE) as u64) <= self.sectors as u64
+    }
+
+    fn read(&self, lba: u32, buf: &mut [u8]) -> bool {
+        if !self.check_range(lba, buf.len()) {
+            return false;
+        }
+
+        buf.chunks_mut(IDE_MAX_SECTORS * SECTOR_SIZE)
+            .enumerate()
+            .all(|(index, chunk)| {
+                let lba = lba + (index * IDE_MAX_SECTORS) as u32;
+                Self::command(lba, chunk.len() / SECTOR_SIZE, IDE_CMD_READ)
+                    && chunk
+                        .chunks_exact_mut(SECTOR_SIZE)
+                        .all(Self::read_sector)
+            })
+    }
+
+    fn write(&self, lba: u32, buf: &[u8]) -> bool {
+        if !self.check_range(lba, buf.len()) {
+            return false;
+        }
+
+        let written = buf
+            .chunks(IDE_MAX_SECTORS * SECTOR_SIZE)
+            .enumerate()
+            .all(|(index, chunk)| {
+                let lba = lba + (index * IDE_MAX_SECTORS) as u32;
+                Self::command(lba, chunk.len() / SECTOR_SIZE, IDE_CMD_WRITE)
+                    && chunk.chunks_exact(SECTOR_SIZE).all(Self::write_sector)
+            });
+
+        // 写完之后刷新硬盘的缓存
+        written && Self::command(0, 0, IDE_CMD_FLUSH) && Self::wait(false)
+    }
+}
+
+/// 识别主通道的主盘,没有硬盘或者不支持LBA的时候不能读写
+pub fn init_ide() {
+    unsafe {
+        // 使用轮询,关闭硬盘中断
+        outb(IDE_CONTROL, IDE_CONTROL_NIEN);
+    }
+
+    let mut identify = [0u8; SECTOR_SIZE];
+    if !IdeDisk::command(0, 0, IDE_CMD_IDENTIFY)
+        || !IdeDisk::read_sector(&mut identify)
+    {
+        return;
+    }
+
+    // 第60和61个字是LBA28可以访问的扇区数
+    let sectors = u32::from_le_bytes([
+        identify[120],
+        identify[121],
+        identify[122],
+        identify[123],
+    ]);
+    if sectors != 0 {
+        *IDE_DISK.lock() = Some(IdeDisk { sectors });
+    }
+}
+
+/// 硬盘的扇区数,没有硬盘返回0
+pub fn ide_sectors() -> u32 {
+    IDE_DISK.lock().map_or(0, |disk| disk.sectors)
+}
+
+/// 从扇区lba开始读取,buf的长度必须是扇区的整数倍,失败返回false
+pub fn ide_read(lba: u32, buf: &mut [u8]) -> bool {
+    let disk = IDE_DISK.lock();
+    disk.is_some_and(|disk| disk.read(lba, buf))
+}
+
+/// 从扇区lba开始写入,buf的长度必须是扇区的整数倍,失败返回false
+pub fn ide_write(lba: u32, buf: &[u8]) -> bool {
+    let disk = IDE_DISK.lock();
+    disk.is_some_and(|disk| disk.write(lba, buf))
+}
@@ -109,7 +109,7 @@ pub fn read_keyboard(buffer: &mut [char]) {
                 let current_task = Task::current_task();
                 WAITER = Some(current_task);
                 // 阻塞当前任务,等待输入
-                Task::block(current_task, TaskState::TaskWaiting, None);
+                Task::block(current_task, TaskState::TaskBlocked, None);
             }
 
             // 到这buffer就不可能是空了
@@ -1,7 +1,51 @@
-  .section .text.entry
+  .section .text.entry, "ax"
   .globl _start
  _start:
-  mov esp, 0x10000
+  mov esp, {load_address}
+
+  // 启动页表,映射低端8M的物理内存,页表是连续的
+  mov edi, {kernel_page_table}
+  xor ecx, ecx
+  mov edx, 0x3
+.Lfill_page_table:
+  mov [edi + ecx * 4], edx
+  add edx, 0x1000
+  inc ecx
+  cmp ecx, {boot_pages}
+  jne .Lfill_page_table
+
+  // 启动页目录,低端地址和内核地址映射到同样的物理内存
+  mov edi, {boot_page_dir}
+  xor ecx, ecx
+.Lclear_page_dir:
+  mov dword ptr [edi + ecx * 4], 0
+  inc ecx
+  cmp ecx, 1024
+  jne .Lclear_page_dir
+
+  mov edx, {kernel_page_table}
+  or edx, 0x3
+  mov [edi], edx
+  mov [edi + {kernel_pde_offset}], edx
+  add edx, 0x1000
+  mov [edi + 4], edx
+  mov [edi + {kernel_pde_offset} + 4], edx
+
+  // 开启分页
+  mov cr3, edi
+  mov ecx, cr0
+  or ecx, 0x80000000
+  mov cr0, ecx
+
+  // 跳转到高地址
+  lea ecx, [higher_half]
+  jmp ecx
+
+  .section .text
+ higher_half:
+  add esp, {kernel_base}
+  // ards 数量指针是物理地址
+  add ebx, {kernel_base}
 
   push ebx
   push eax
@@ -10,4 +54,4 @@
   call init_gdt
   call init_tss
   call init_mem_mapping
-  call rust_main
\ No newline at end of file
+  call rust_main
@@ -0,0 +1,79 @@
+  .section .text.entry, "ax"
+  .globl _start
+ _start:
+  mov esp, {load_address}
+
+  // 启动页表,映射低端8M的物理内存,页表是连续的,每一项8个字节
+  mov edi, {kernel_page_table}
+  xor ecx, ecx
+  mov edx, 0x3
+.Lfill_page_table:
+  mov [edi + ecx * 8], edx
+  mov dword ptr [edi + ecx * 8 + 4], 0
+  add edx, 0x1000
+  inc ecx
+  cmp ecx, {boot_pages}
+  jne .Lfill_page_table
+
+  // 启动页目录,前几项指向启动页表
+  mov edi, {boot_page_table_dir}
+  xor ecx, ecx
+.Lclear_page_dir:
+  mov dword ptr [edi + ecx * 4], 0
+  inc ecx
+  cmp ecx, 1024
+  jne .Lclear_page_dir
+
+  mov edx, {kernel_page_table}
+  or edx, 0x3
+  xor ecx, ecx
+.Lfill_page_dir:
+  mov [edi + ecx * 8], edx
+  add edx, 0x1000
+  inc ecx
+  cmp ecx, {boot_tables}
+  jne .Lfill_page_dir
+
+  // 启动页目录指针表,低端地址和内核地址共用启动页目录
+  // 页目录指针表项只能设置存在位
+  mov esi, {boot_page_dir}
+  xor ecx, ecx
+.Lclear_pdpt:
+  mov dword ptr [esi + ecx * 4], 0
+  inc ecx
+  cmp ecx, 8
+  jne .Lclear_pdpt
+
+  or edi, 0x1
+  mov [esi], edi
+  mov [esi + {kernel_pdpte_offset}], edi
+
+  // 开启PAE
+  mov ecx, cr4
+  or ecx, 0x20
+  mov cr4, ecx
+
+  // 开启分页
+  mov cr3, esi
+  mov ecx, cr0
+  or ecx, 0x80000000
+  mov cr0, ecx
+
+  // 跳转到高地址
+  lea ecx, [higher_half]
+  jmp ecx
+
+  .section .text
+ higher_half:
+  add esp, {kernel_base}
+  // ards 数量指针是物理地址
+  add ebx, {kernel_base}
+
+  push ebx
+  push eax
+
+  call memory_init
+  call init_gdt
+  call init_tss
+  call init_mem_mapping
+  call rust_main
@@ -1,4 +1,5 @@
 use crate::kernel::sync::mutex::Mutex;
+use crate::mm::page::KERNEL_PAGE_DIR;
 use core::mem::size_of;
 use lazy_static::lazy_static;
 use x86::bits32::task::TaskStateSegment;
@@ -24,6 +25,11 @@ const KERNEL_TSS_IDX: usize = 3;
 const USER_CODE_IDX: usize = 4;
 /// 用户数据段全局描述符表索引
 const USER_DATA_IDX: usize = 5;
+/// 双重错误TSS描述符索引
+const DOUBLE_FAULT_TSS_IDX: usize = 6;
+
+/// 段界限 4G / 4K - 1,内核在高地址,段需要覆盖全部的线性地址
+const SEGMENT_LIMIT: u32 = 0xfffff;
 
 pub const KERNEL_CODE_SELECTOR: SegmentSelector =
     SegmentSelector::new(KERNEL_CODE_IDX as _, Ring0);
@@ -35,9 +41,13 @@ pub const USER_CODE_SELECTOR: SegmentSelector =
     SegmentSelector::new(USER_CODE_IDX as _, Ring3);
 pub const USER_DATA_SELECTOR: SegmentSelector =
     SegmentSelector::new(USER_DATA_IDX as _, Ring3);
+pub const DOUBLE_FAULT_TSS_SELECTOR: SegmentSelector =
+    SegmentSelector::new(DOUBLE_FAULT_TSS_IDX as _, Ring0);
 
 /// TSS描述符
 pub static mut TSS: TaskStateSegment = TaskStateSegment::new();
+/// 双重错误通过任务门切换到这个TSS,使用单独的栈
+static mut DOUBLE_FAULT_TSS: TaskStateSegment = TaskStateSegment::new();
 
 // 内核全局描述符
 lazy_static! {
@@ -56,7 +66,7 @@ pub fn init_gdt() {
     // 内核代码段
     gdt_guard[KERNEL_CODE_IDX] = DescriptorBuilder::code_descriptor(
         0,                            // 描述的内存起始位置
-        0xffff,                       // 结束位置
+        SEGMENT_LIMIT,                // 结束位置
         CodeSegmentType::ExecuteRead, // 0b1010 代码段/非依从/可读/没有被访问过
     )
     .limit_granularity_4kb() // 4k
@@ -68,7 +78,7 @@ pub fn init_gdt() {
     // 内核数据段
     gdt_guard[KERNEL_DATA_IDX] = DescriptorBuilder::data_descriptor(
         0,                          // 描述的内存起始位置
-        0xffff,                     // 结束位置
+        SEGMENT_LIMIT,              // 结束位置
         DataSegmentType::ReadWrite, // 0b0010 数据段/向上增长/可写/没有被访问过
     )
     .limit_granularity_4kb() // 4k
@@ -80,7 +90,7 @@ pub fn init_gdt() {
     // 用户代码段
     gdt_guard[USER_CODE_IDX] = DescriptorBuilder::code_descriptor(
         0,                            // 描述的内存起始位置
-        0xffff,                       // 结束位置
+        SEGMENT_LIMIT,                // 结束位置
         CodeSegmentType::ExecuteRead, // 0b1010 代码段/非依从/可读/没有被访问过
     )
     .limit_granularity_4kb() // 4k
@@ -92,7 +102,7 @@ pub fn init_gdt() {
     //  用户数据段
     gdt_guard[USER_DATA_IDX] = DescriptorBuilder::data_descriptor(
         0,                          // 描述的内存起始位置
-        0xffff,                     // 结束位置
+        SEGMENT_LIMIT,              // 结束位置
         DataSegmentType::ReadWrite, // 0b0010 数据段/向上增长/可写/没有被访问过
     )
     .limit_granularity_4kb() // 4k
@@ -129,3 +139,29 @@ pub fn init_tss() {
         load_tr(KERNEL_TSS_SELECTOR);
     }
 }
+
+/// 初始化双重错误的TSS,内核栈溢出的时候原来的栈已经不能用了
+pub fn init_double_fault_tss(handler: u32, stack_top: u32) {
+    unsafe {
+        DOUBLE_FAULT_TSS.eip = handler;
+        DOUBLE_FAULT_TSS.esp = stack_top;
+        DOUBLE_FAULT_TSS.cr3 = KERNEL_PAGE_DIR;
+        // 关闭中断
+        DOUBLE_FAULT_TSS.eflags = 0b10;
+        DOUBLE_FAULT_TSS.cs = KERNEL_CODE_SELECTOR.bits();
+        DOUBLE_FAULT_TSS.ss = KERNEL_DATA_SELECTOR.bits();
+        DOUBLE_FAULT_TSS.ds = KERNEL_DATA_SELECTOR.bits();
+        DOUBLE_FAULT_TSS.es = KERNEL_DATA_SELECTOR.bits();
+        DOUBLE_FAULT_TSS.fs = KERNEL_DATA_SELECTOR.bits();
+        DOUBLE_FAULT_TSS.gs = KERNEL_DATA_SELECTOR.bits();
+    }
+
+    GDT.lock()[DOUBLE_FAULT_TSS_IDX] =
+        <DescriptorBuilder as GateDescriptorBuilder<u32>>::tss_descriptor(
+            unsafe { &DOUBLE_FAULT_TSS as *const TaskStateSegment as u64 },
+            size_of::<TaskStateSegment>() as u64 - 1,
+            true,
+        )
+        .present() // 存在内存
+        .finish();
+}
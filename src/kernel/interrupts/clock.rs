@@ -1,7 +1,9 @@
+use crate::kernel::global::USER_CODE_SELECTOR;
 use crate::kernel::interrupts::handler::set_interrupt_handler;
 use crate::kernel::interrupts::pic::pic_controller::send_eoi;
 use crate::kernel::interrupts::{set_interrupt_mask, IRQ_CLOCK, IRQ_MASTER_NR};
 use crate::kernel::sync::mutex::Mutex;
+use crate::kernel::tasks::run_queue::BOOST_INTERVAL;
 use crate::kernel::tasks::task::Task;
 use crate::KERNEL_MAGIC;
 
@@ -42,7 +44,7 @@ pub extern "C" fn clock_handler(
     _vector0: u32,
     _error_code: u32,
     _eip: u32,
-    _cs: u32,
+    cs: u32,
     _eflags: u32,
 ) {
     use core::ops::AddAssign;
@@ -58,6 +60,11 @@ pub extern "C" fn clock_handler(
         // 尝试唤醒
         Task::wake_up();
 
+        // 定期提升所有任务的级别,被降级的任务不会饿死
+        if *JIFFIES.lock() & (BOOST_INTERVAL - 1) == 0 {
+            Task::boost();
+        }
+
         let mut current = Task::current_task();
         // 内核栈溢出检测
         assert_eq!(
@@ -66,6 +73,15 @@ pub extern "C" fn clock_handler(
             "{:p}",
             current
         );
+        // 被OOM杀死的任务,从用户态进入中断的时候退出
+        if current.as_ref().killed && cs == USER_CODE_SELECTOR.bits() as u32 {
+            assert!(
+                Task::is_killable(current),
+                "task {} can not be killed",
+                current.as_ref().name
+            );
+            Task::kill();
+        }
         // 全局时间片
         current.as_mut().jiffies = *JIFFIES.lock();
         // 可用时间片-1
@@ -75,8 +91,8 @@ pub extern "C" fn clock_handler(
 
         // 可用时间片使用完毕
         if current.as_mut().ticks == 0 {
-            // 重置可用时间片
-            current.as_mut().ticks = current.as_ref().priority as u64;
+            // 降低一级并重置可用时间片
+            Task::demote(current);
             // 调度到别的任务
             Task::schedule();
         }
@@ -0,0 +1,55 @@
+use crate::mm::paging::BASE_PAGE_SIZE;
+use x86::controlregs::cr2;
+use x86::irq::DOUBLE_FAULT_VECTOR;
+use x86::segmentation::{
+    BuildDescriptor, DescriptorBuilder, TaskGateDescriptorBuilder,
+};
+use x86::Ring::Ring0;
+
+use crate::kernel::global::{
+    init_double_fault_tss, DOUBLE_FAULT_TSS_SELECTOR, TSS,
+};
+use crate::kernel::interrupts::idt::INTERRUPT_ENTRY;
+use crate::kernel::tasks::task::Task;
+use crate::mm::kernel_stack::is_kernel_stack_guard;
+
+/// 双重错误处理函数使用的栈
+static mut DOUBLE_FAULT_STACK: [u8; BASE_PAGE_SIZE] = [0; BASE_PAGE_SIZE];
+
+/// 双重错误处理函数,通过任务门进入,不会返回
+/// 内核栈溢出到保护页时,缺页中断无法压栈,就会变成双重错误
+extern "C" fn double_fault_handler() -> ! {
+    // 出错时的寄存器保存在原来的TSS中
+    let (eip, esp) = unsafe { (TSS.eip, TSS.esp) };
+    let vaddr = unsafe { cr2() } as u32;
+    let name = unsafe { Task::current_task().as_ref().name };
+
+    if is_kernel_stack_guard(vaddr) {
+        panic!(
+            "[DOUBLE FAULT] task {} kernel stack overflow at {:#x}, eip:{:#x}, esp:{:#x}",
+            name, vaddr, eip, esp
+        );
+    }
+
+    panic!(
+        "[DOUBLE FAULT] task {} eip:{:#x}, esp:{:#x}, cr2:{:#x}",
+        name, eip, esp, vaddr
+    );
+}
+
+pub fn init_double_fault() {
+    let stack_top =
+        unsafe { DOUBLE_FAULT_STACK.as_ptr() as usize + BASE_PAGE_SIZE };
+    init_double_fault_tss(
+        double_fault_handler as usize as u32,
+        stack_top as u32,
+    );
+
+    INTERRUPT_ENTRY.lock()[DOUBLE_FAULT_VECTOR as usize] =
+        <DescriptorBuilder as TaskGateDescriptorBuilder>::task_gate_descriptor(
+            DOUBLE_FAULT_TSS_SELECTOR,
+        )
+        .dpl(Ring0)
+        .present()
+        .finish();
+}
@@ -1,15 +1,19 @@
 use crate::drivers::keyboard::init_keyboard;
 use crate::kernel::interrupts::clock::init_clock;
+use crate::kernel::interrupts::double_fault::init_double_fault;
 use crate::kernel::interrupts::idt::init_idt;
+use crate::kernel::interrupts::page_fault::init_page_fault;
 use crate::kernel::interrupts::pic::pic_controller::init_pic;
 use crate::kernel::interrupts::pic::{PIC_M_DATA, PIC_S_DATA};
 use core::arch::asm;
 use x86::bits32::eflags::{self, EFlags};
 
 pub mod clock;
+pub mod double_fault;
 pub mod handler;
 pub mod handler_entry;
 pub mod idt;
+pub mod page_fault;
 pub mod pic;
 
 /// IDT的大小
@@ -54,6 +58,8 @@ pub const IRQ_HARDDISK2: u8 = 15; // ATA 硬盘第二通道
 pub fn init_interrupt() {
     init_pic();
     init_idt();
+    init_double_fault();
+    init_page_fault();
     init_clock();
     init_keyboard();
 }
@@ -0,0 +1,140 @@
+use x86::controlregs::cr2;
+use x86::irq::{PageFaultError, PAGE_FAULT_VECTOR};
+
+use crate::kernel::interrupts::handler::set_interrupt_handler;
+use crate::kernel::tasks::task::Task;
+use crate::mm::kernel_stack::is_kernel_stack_guard;
+use crate::mm::oom::out_of_memory;
+use crate::mm::page::{
+    copy_on_write, map_zeroed_page, page_swap_slot, sync_kernel_pde, PageIndex,
+};
+use crate::mm::paging::PTFlags;
+use crate::mm::swap::swap_in;
+use crate::mm::vma::VmKind;
+use crate::printlnk;
+
+/// 缺页中断处理函数
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn page_fault_handler(
+    vector: u32,
+    _edi: u32,
+    _esi: u32,
+    _ebp: u32,
+    _esp: u32,
+    _ebx: u32,
+    _edx: u32,
+    _ecx: u32,
+    _eax: u32,
+    _gs: u32,
+    _fs: u32,
+    _es: u32,
+    _ds: u32,
+    _vector0: u32,
+    error_code: u32,
+    eip: u32,
+    _cs: u32,
+    _eflags: u32,
+) {
+    assert_eq!(vector, PAGE_FAULT_VECTOR as u32);
+
+    // 引起缺页的线性地址
+    let vaddr = unsafe { cr2() } as u32;
+    let error = PageFaultError::from_bits_truncate(error_code);
+    let current = Task::current_task();
+
+    unsafe {
+        // 被OOM杀死的任务,回到用户态之前退出
+        if error.contains(PageFaultError::US) && current.as_ref().killed {
+            assert!(
+                Task::is_killable(current),
+                "task {} can not be killed",
+                current.as_ref().name
+            );
+            Task::kill();
+        }
+
+        // 内核新建的页表还没有同步到当前地址空间
+        if !error.contains(PageFaultError::P) && sync_kernel_pde(vaddr) {
+            return;
+        }
+
+        if let Some(area) = current.as_ref().vm_areas.find(vaddr) {
+            // 页不存在,且地址在任务登记的区域内,换出的页从交换区读回,否则映射一个清零的页
+            // 写只读页,且区域是可写的,说明是fork之后共享的页,写时复制
+            let handled = if !error.contains(PageFaultError::P)
+                && area.flags.contains(PTFlags::US)
+                && area.kind != VmKind::Code
+            {
+                let page = vaddr.idx_mask();
+                if page_swap_slot(page).is_some() {
+                    Some(swap_in(page, area.flags))
+                } else {
+                    Some(map_zeroed_page(page, area.flags))
+                }
+            } else if error.contains(PageFaultError::WR)
+                && area.flags.contains(PTFlags::RW)
+            {
+                Some(copy_on_write(vaddr.idx_mask()))
+            } else {
+                None
+            };
+
+            match handled {
+                Some(true) => return,
+                Some(false) => {
+                    // 杀死占用内存最多的任务,之后重新执行引起缺页的指令
+                    if out_of_memory() {
+                        return;
+                    }
+                    // init不能退出,没有其他任务可以杀死的时候只能停机
+                    if !Task::is_killable(current) {
+                        panic!(
+                            "task {} out of memory at {:#x}",
+                            current.as_ref().name,
+                            vaddr
+                        );
+                    }
+                    printlnk!(
+                        "[PAGE FAULT] task {} out of memory at {:#x}",
+                        current.as_ref().name,
+                        vaddr
+                    );
+                    Task::kill();
+                }
+                None => {}
+            }
+        }
+
+        // 用户态的非法访问,杀死任务
+        if error.contains(PageFaultError::US) {
+            printlnk!(
+                "[PAGE FAULT] task {} invalid access {:#x}, eip:{:#x}, error:{:#b}",
+                current.as_ref().name,
+                vaddr,
+                eip,
+                error_code
+            );
+            Task::kill();
+        }
+    }
+
+    // 内核栈溢出到了保护页
+    if is_kernel_stack_guard(vaddr) {
+        panic!(
+            "[PAGE FAULT] task {} kernel stack overflow at {:#x}, eip:{:#x}",
+            unsafe { current.as_ref().name },
+            vaddr,
+            eip
+        );
+    }
+
+    // 内核态的非法访问
+    panic!(
+        "[PAGE FAULT] kernel invalid access {:#x}, eip:{:#x}, error:{:#b}",
+        vaddr, eip, error_code
+    );
+}
+
+pub fn init_page_fault() {
+    set_interrupt_handler(PAGE_FAULT_VECTOR as usize, page_fault_handler);
+}
@@ -0,0 +1,38 @@
+/// 系统调用的错误码,和linux保持一致
+/// 系统调用失败的时候返回错误码的相反数
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(usize)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Errno {
+    /// 对象不存在
+    ENOENT = 2,
+    /// 参数太长
+    E2BIG = 7,
+    /// 不是可以执行的文件
+    ENOEXEC = 8,
+    /// 错误的文件描述符
+    EBADF = 9,
+    /// 没有子任务
+    ECHILD = 10,
+    /// 内存不足
+    ENOMEM = 12,
+    /// 没有权限
+    EACCES = 13,
+    /// 错误的地址
+    EFAULT = 14,
+    /// 对象已经存在
+    EEXIST = 17,
+    /// 参数错误
+    EINVAL = 22,
+    /// 没有空间
+    ENOSPC = 28,
+    /// 文件名太长
+    ENAMETOOLONG = 36,
+}
+
+impl Errno {
+    /// 转换成系统调用的返回值
+    pub fn as_ret(self) -> usize {
+        (self as usize).wrapping_neg()
+    }
+}
@@ -1,7 +1,7 @@
 use crate::kernel::system_call::SYS_CALL_SIZE;
 use crate::printlnk;
 
-type SystemCall = extern "C" fn(usize, usize, usize, usize) -> usize;
+type SystemCall = extern "C" fn(usize, usize, usize, usize, usize) -> usize;
 
 #[no_mangle]
 pub static mut SYSTEM_CALL_TABLE: [SystemCall; SYS_CALL_SIZE] = {
@@ -16,8 +16,16 @@ pub extern "C" fn default_sys_call(
     ebx: usize,
     ecx: usize,
     edx: usize,
+    esi: usize,
     vector: usize,
 ) -> usize {
-    printlnk!("vector:{}, eax:{}, ebx:{} ecx:{}", vector, ebx, ecx, edx);
+    printlnk!(
+        "vector:{}, ebx:{} ecx:{} edx:{} esi:{}",
+        vector,
+        ebx,
+        ecx,
+        edx,
+        esi
+    );
     0
 }
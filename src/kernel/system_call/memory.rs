@@ -0,0 +1,333 @@
+use core::mem::size_of;
+use core::slice;
+
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::system_call::sys_call::SysCall;
+use crate::kernel::system_call::{
+    sys_call_1, sys_call_2, sys_call_3, sys_call_4,
+};
+use crate::kernel::tasks::task::Task;
+use crate::mm::meminfo::{mem_info, MemInfo};
+use crate::mm::page::{
+    free_range, is_user_addr, map_zeroed_page, protect_range, PageIndex,
+};
+use crate::mm::paging::BASE_PAGE_SIZE;
+use crate::mm::uaccess::copy_to_user;
+use crate::mm::vma::{
+    MmapFlags, MmapProt, VmArea, VmAreas, VmKind, USER_STACK_SIZE,
+    USER_STACK_TOP,
+};
+
+/// mmap和munmap失败的返回值
+pub const MAP_FAILED: usize = usize::MAX;
+
+/// 设置堆的结束位置,返回新的结束位置,失败返回原来的结束位置
+/// addr为0时只查询当前的结束位置
+#[inline(always)]
+pub fn sys_brk(addr: usize) -> usize {
+    sys_call_1(SysCall::Brk, addr)
+}
+
+/// 堆增长increment字节,返回原来的结束位置,失败返回usize::MAX
+pub fn sys_sbrk(increment: isize) -> usize {
+    let old = sys_brk(0);
+    if increment == 0 {
+        return old;
+    }
+
+    let new = old.wrapping_add_signed(increment);
+    if sys_brk(new) != new {
+        return usize::MAX;
+    }
+    old
+}
+
+/// 映射一段匿名内存,返回起始地址,失败返回MAP_FAILED
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+) -> usize {
+    sys_call_4(
+        SysCall::Mmap,
+        addr,
+        len,
+        prot.bits() as usize,
+        flags.bits() as usize,
+    )
+}
+
+/// 取消[addr, addr + len)的映射,成功返回0
+#[inline(always)]
+pub fn sys_munmap(addr: usize, len: usize) -> usize {
+    sys_call_2(SysCall::Munmap, addr, len)
+}
+
+/// 修改[addr, addr + len)的内存保护属性,成功返回0,失败返回错误码的相反数
+#[inline(always)]
+pub fn sys_mprotect(addr: usize, len: usize, prot: MmapProt) -> usize {
+    sys_call_3(SysCall::Mprotect, addr, len, prot.bits() as usize)
+}
+
+/// 把内存的统计信息写入info,成功返回0,失败返回错误码的相反数
+#[inline(always)]
+pub fn sys_meminfo(info: *mut MemInfo) -> usize {
+    sys_call_1(SysCall::Meminfo, info as usize)
+}
+
+/// 按页向上对齐,超出用户空间返回None
+pub(crate) fn page_align_up(addr: usize) -> Option<u32> {
+    let addr = addr.checked_add(BASE_PAGE_SIZE - 1)?.idx_mask();
+    (addr <= USER_STACK_TOP as usize).then_some(addr as u32)
+}
+
+/// 用户栈的最低位置,堆和映射区都不能超过这里
+pub(crate) const fn stack_bottom() -> u32 {
+    USER_STACK_TOP - USER_STACK_SIZE
+}
+
+/// 为大小为size的映射选择起始地址,在堆和栈之间
+/// fixed为true时必须使用addr,否则addr只是建议,不可用的时候另外查找
+/// 内核任务没有用户堆,返回None
+pub(crate) fn find_user_range(
+    vm_areas: &mut VmAreas,
+    addr: usize,
+    size: u32,
+    fixed: bool,
+) -> Option<u32> {
+    let heap = vm_areas.heap()?;
+    let low = page_align_up(heap.end as usize).unwrap();
+    let high = stack_bottom();
+
+    // 地址是否是页对齐的、空闲的用户地址,第0页留给空指针
+    let is_free = |start: usize| {
+        start & (BASE_PAGE_SIZE - 1) == 0
+            && start >= BASE_PAGE_SIZE
+            && start
+                .checked_add(size as usize)
+                .is_some_and(|end| end <= high as usize)
+            && vm_areas
+                .overlapped(start as u32, start as u32 + size)
+                .count()
+                == 0
+    };
+
+    if fixed {
+        is_free(addr).then_some(addr as u32)
+    } else if addr != 0 && is_free(addr) {
+        Some(addr as u32)
+    } else {
+        vm_areas.find_free(size, low, high)
+    }
+}
+
+/// 下面是系统调用的具体实现
+pub(crate) extern "C" fn task_brk(
+    addr: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 内核任务没有用户堆,堆被mprotect拆分之后只能在最高的一段中移动
+    let Some(heap) = vm_areas.heap() else {
+        return 0;
+    };
+    let (start, old_end) = (heap.start, heap.end);
+
+    if addr < start as usize || addr > stack_bottom() as usize {
+        return old_end as usize;
+    }
+
+    let new_end = addr as u32;
+    let old_top = page_align_up(old_end as usize).unwrap();
+    let new_top = page_align_up(addr).unwrap();
+
+    // 增长的部分不能和其他区域重叠
+    if new_top > old_top && vm_areas.overlapped(old_top, new_top).count() != 0 {
+        return old_end as usize;
+    }
+
+    // 缩小的部分释放已经映射的物理页
+    if new_top < old_top {
+        free_range(new_top, (old_top - new_top) as usize);
+    }
+
+    vm_areas.heap().unwrap().end = new_end;
+    new_end as usize
+}
+
+pub(crate) extern "C" fn task_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    _: usize,
+) -> usize {
+    let prot = MmapProt::from_bits_truncate(prot as u32);
+    let flags = MmapFlags::from_bits_truncate(flags as u32);
+
+    // 只支持匿名映射,共享和私有必须二选一
+    if len == 0
+        || !flags.contains(MmapFlags::ANONYMOUS)
+        || flags.contains(MmapFlags::SHARED)
+            == flags.contains(MmapFlags::PRIVATE)
+    {
+        return MAP_FAILED;
+    }
+
+    let Some(size) = page_align_up(len) else {
+        return MAP_FAILED;
+    };
+
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 内核任务不能映射用户内存
+    let fixed = flags.contains(MmapFlags::FIXED);
+    let Some(start) = find_user_range(vm_areas, addr, size, fixed) else {
+        return MAP_FAILED;
+    };
+
+    let kind = if flags.contains(MmapFlags::SHARED) {
+        VmKind::Shared
+    } else {
+        VmKind::Anonymous
+    };
+    let area = VmArea::new(start, start + size, prot.page_flags(), kind);
+    if !vm_areas.insert(area) {
+        return MAP_FAILED;
+    }
+
+    // 共享映射立即分配物理页,fork之后父子任务看到的是同一份物理页
+    if kind == VmKind::Shared {
+        for page in (start..start + size).step_by(BASE_PAGE_SIZE) {
+            if !map_zeroed_page(page, area.flags) {
+                free_range(start, size as usize);
+                vm_areas.remove_range(start, start + size);
+                return MAP_FAILED;
+            }
+        }
+    }
+
+    start as usize
+}
+
+pub(crate) extern "C" fn task_munmap(
+    addr: usize,
+    len: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    if addr & (BASE_PAGE_SIZE - 1) != 0 || len == 0 {
+        return MAP_FAILED;
+    }
+
+    let Some(end) = addr.checked_add(len).and_then(page_align_up) else {
+        return MAP_FAILED;
+    };
+    let start = addr as u32;
+
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 代码段、栈、堆和共享内存段不能通过munmap释放
+    if vm_areas.overlapped(start, end).any(|area| {
+        matches!(
+            area.kind,
+            VmKind::Code | VmKind::Stack | VmKind::Heap | VmKind::Shm(_)
+        )
+    }) {
+        return MAP_FAILED;
+    }
+
+    if !vm_areas.remove_range(start, end) {
+        return MAP_FAILED;
+    }
+    free_range(start, (end - start) as usize);
+
+    0
+}
+
+pub(crate) extern "C" fn task_mprotect(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    if addr & (BASE_PAGE_SIZE - 1) != 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    if len == 0 {
+        return 0;
+    }
+
+    // 不能修改内核的映射
+    let Some(end) = addr.checked_add(len).and_then(page_align_up) else {
+        return Errno::EFAULT.as_ret();
+    };
+    let start = addr as u32;
+    if !is_user_addr(start) {
+        return Errno::EFAULT.as_ret();
+    }
+
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 范围内必须都是登记过的区域
+    if !vm_areas.covers(start, end) {
+        return Errno::ENOMEM.as_ret();
+    }
+
+    // 代码段是内核镜像中的页,共享内存段按编号挂载,都不能修改
+    if vm_areas
+        .overlapped(start, end)
+        .any(|area| matches!(area.kind, VmKind::Code | VmKind::Shm(_)))
+    {
+        return Errno::EACCES.as_ret();
+    }
+
+    let flags = MmapProt::from_bits_truncate(prot as u32).page_flags();
+    if !vm_areas.protect(start, end, flags) {
+        return Errno::ENOMEM.as_ret();
+    }
+
+    // 已经映射的页立即修改,没有映射的页缺页时按新的属性映射
+    vm_areas.overlapped(start, end).for_each(|area| {
+        protect_range(
+            area.start,
+            (area.end - area.start) as usize,
+            flags,
+            area.kind.is_shared(),
+        );
+    });
+
+    0
+}
+
+pub(crate) extern "C" fn task_meminfo(
+    info: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let meminfo = mem_info();
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            &meminfo as *const MemInfo as *const u8,
+            size_of::<MemInfo>(),
+        )
+    };
+
+    match copy_to_user(info, bytes) {
+        Ok(()) => 0,
+        Err(errno) => errno.as_ret(),
+    }
+}
@@ -1,10 +1,22 @@
+pub mod errno;
 mod gate;
+pub mod memory;
 pub mod print;
+pub mod shm;
 pub mod sys_call;
 
 use crate::kernel::system_call::gate::{default_sys_call, SYSTEM_CALL_TABLE};
+use crate::kernel::system_call::memory::{
+    task_brk, task_meminfo, task_mmap, task_mprotect, task_munmap,
+};
 use crate::kernel::system_call::print::write_char;
-use crate::kernel::system_call::sys_call::{task_sleep, task_yield, SysCall};
+use crate::kernel::system_call::shm::{
+    task_shmat, task_shmctl, task_shmdt, task_shmget,
+};
+use crate::kernel::system_call::sys_call::{
+    task_execve, task_exit, task_fork, task_getpid, task_getppid, task_sleep,
+    task_waitpid, task_yield, SysCall,
+};
 use core::arch::asm;
 
 pub const SYS_CALL_SIZE: usize = 20;
@@ -29,12 +41,13 @@ pub extern "C" fn system_call() {
         "push %gs",
         "pusha",
         "push $0x80", // 向中断处理函数传递参数中断向量 vector
+        "push %esi", // 第四个参数
         "push %edx", // 第三个参数
         "push %ecx", // 第二个参数
         "push %ebx", // 第一个参数
         // 调用系统调用处理函数，syscall_table 中存储了系统调用处理函数的指针
         "call *{1}(,%eax,4)",
-        "add $12, %esp",
+        "add $16, %esp",
         // 修改栈中 %eax 寄存器，设置系统调用返回值
         "mov %eax, 32(%esp)",
         // 中断返回
@@ -63,6 +76,8 @@ pub(crate) extern "C" fn sys_call_check(sys_call_number: u32) {
     );
 }
 
+/// 用户态只能执行用户代码段中的代码,系统调用的封装必须内联到调用者中
+#[inline(always)]
 pub(crate) fn sys_call(sys_call: SysCall) -> usize {
     let res: usize;
     unsafe {
@@ -75,6 +90,7 @@ pub(crate) fn sys_call(sys_call: SysCall) -> usize {
     }
 }
 
+#[inline(always)]
 pub(crate) fn sys_call_1(sys_call: SysCall, arg1: usize) -> usize {
     let res: usize;
     unsafe {
@@ -88,6 +104,7 @@ pub(crate) fn sys_call_1(sys_call: SysCall, arg1: usize) -> usize {
     }
 }
 
+#[inline(always)]
 pub(crate) fn sys_call_2(sys_call: SysCall, arg1: usize, arg2: usize) -> usize {
     let res: usize;
     unsafe {
@@ -102,6 +119,7 @@ pub(crate) fn sys_call_2(sys_call: SysCall, arg1: usize, arg2: usize) -> usize {
     }
 }
 
+#[inline(always)]
 pub(crate) fn sys_call_3(
     sys_call: SysCall,
     arg1: usize,
@@ -122,11 +140,49 @@ pub(crate) fn sys_call_3(
     }
 }
 
+#[inline(always)]
+pub(crate) fn sys_call_4(
+    sys_call: SysCall,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+) -> usize {
+    let res: usize;
+    unsafe {
+        asm!(
+        "int $0x80",
+        inout("eax") sys_call as usize => res,
+        in("ebx") arg1,
+        in("ecx") arg2,
+        in("edx") arg3,
+        in("esi") arg4,
+        options(att_syntax)
+        );
+        res
+    }
+}
+
 pub fn init_system_call() {
     unsafe {
         SYSTEM_CALL_TABLE[SysCall::Test as usize] = default_sys_call;
         SYSTEM_CALL_TABLE[SysCall::Yield as usize] = task_yield;
         SYSTEM_CALL_TABLE[SysCall::Sleep as usize] = task_sleep;
         SYSTEM_CALL_TABLE[SysCall::Write as usize] = write_char;
+        SYSTEM_CALL_TABLE[SysCall::Fork as usize] = task_fork;
+        SYSTEM_CALL_TABLE[SysCall::Brk as usize] = task_brk;
+        SYSTEM_CALL_TABLE[SysCall::Mmap as usize] = task_mmap;
+        SYSTEM_CALL_TABLE[SysCall::Munmap as usize] = task_munmap;
+        SYSTEM_CALL_TABLE[SysCall::Shmget as usize] = task_shmget;
+        SYSTEM_CALL_TABLE[SysCall::Shmat as usize] = task_shmat;
+        SYSTEM_CALL_TABLE[SysCall::Shmdt as usize] = task_shmdt;
+        SYSTEM_CALL_TABLE[SysCall::Shmctl as usize] = task_shmctl;
+        SYSTEM_CALL_TABLE[SysCall::Meminfo as usize] = task_meminfo;
+        SYSTEM_CALL_TABLE[SysCall::Mprotect as usize] = task_mprotect;
+        SYSTEM_CALL_TABLE[SysCall::Exit as usize] = task_exit;
+        SYSTEM_CALL_TABLE[SysCall::Waitpid as usize] = task_waitpid;
+        SYSTEM_CALL_TABLE[SysCall::Getpid as usize] = task_getpid;
+        SYSTEM_CALL_TABLE[SysCall::Getppid as usize] = task_getppid;
+        SYSTEM_CALL_TABLE[SysCall::Execve as usize] = task_execve;
     }
 }
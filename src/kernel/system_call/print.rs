@@ -1,7 +1,11 @@
 use crate::drivers::gpu::vga_driver::CONSOLE;
+use crate::kernel::system_call::errno::Errno;
 use crate::kernel::system_call::sys_call::SysCall;
 use crate::kernel::system_call::sys_call_3;
-use core::ptr::slice_from_raw_parts;
+use crate::mm::uaccess::copy_from_user;
+
+/// 每次从用户空间拷贝的字节数
+const WRITE_BUFFER_SIZE: usize = 256;
 
 #[repr(C)]
 pub enum StdFd {
@@ -10,25 +14,33 @@ pub enum StdFd {
     Err,
 }
 
-pub fn sys_write(fd: StdFd, str: &str) {
-    sys_call_3(SysCall::Write, fd as _, str.as_ptr() as _, str.len());
+/// 写入[buf, buf + len),返回写入的字节数,地址非法返回-EFAULT
+/// fd不是标准输出或者标准错误返回-EBADF
+#[inline(always)]
+pub fn sys_write(fd: StdFd, buf: *const u8, len: usize) -> usize {
+    sys_call_3(SysCall::Write, fd as _, buf as _, len)
 }
 
 pub(crate) extern "C" fn write_char(
     fd: usize,
     ptr: usize,
     len: usize,
+    _: usize,
     _vector: usize,
 ) -> usize {
-    let slice = unsafe { &*slice_from_raw_parts(ptr as *const u8, len) };
+    // 只能写标准输出和标准错误
+    if !matches!(fd, 1 | 2) {
+        return Errno::EBADF.as_ret();
+    }
 
-    match fd {
-        1 | 2 => {
-            CONSOLE.lock().write_bytes(slice);
-        }
-        _ => {
-            panic!("std in is not impl yet.")
+    // 分段拷贝到内核的缓冲区再输出
+    let mut buffer = [0u8; WRITE_BUFFER_SIZE];
+    for offset in (0..len).step_by(WRITE_BUFFER_SIZE) {
+        let chunk = &mut buffer[..(len - offset).min(WRITE_BUFFER_SIZE)];
+        if let Err(errno) = copy_from_user(chunk, ptr.wrapping_add(offset)) {
+            return errno.as_ret();
         }
+        CONSOLE.lock().write_bytes(chunk);
     }
 
     len
@@ -0,0 +1,153 @@
+use core::mem::size_of;
+use core::slice;
+
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::system_call::memory::{find_user_range, page_align_up};
+use crate::kernel::system_call::sys_call::SysCall;
+use crate::kernel::system_call::{sys_call_1, sys_call_3};
+use crate::kernel::tasks::task::Task;
+use crate::mm::page::free_range;
+use crate::mm::paging::{no_execute, PTFlags};
+use crate::mm::shm::{
+    shm_attach, shm_detach, shm_get, shm_remove, shm_size, shm_stat, ShmFlags,
+    ShmInfo, IPC_RMID, IPC_STAT,
+};
+use crate::mm::uaccess::copy_to_user;
+use crate::mm::vma::{VmArea, VmKind};
+
+/// 按键获取共享内存段,返回段的编号,失败返回错误码的相反数
+#[inline(always)]
+pub fn sys_shmget(key: usize, size: usize, flags: ShmFlags) -> usize {
+    sys_call_3(SysCall::Shmget, key, size, flags.bits() as usize)
+}
+
+/// 挂载共享内存段,addr为0时由内核选择地址,返回挂载的地址
+#[inline(always)]
+pub fn sys_shmat(id: usize, addr: usize, flags: ShmFlags) -> usize {
+    sys_call_3(SysCall::Shmat, id, addr, flags.bits() as usize)
+}
+
+/// 卸载挂载在addr的共享内存段,成功返回0
+#[inline(always)]
+pub fn sys_shmdt(addr: usize) -> usize {
+    sys_call_1(SysCall::Shmdt, addr)
+}
+
+/// 控制共享内存段,IPC_STAT把段的信息写入buf,IPC_RMID标记删除
+#[inline(always)]
+pub fn sys_shmctl(id: usize, cmd: usize, buf: *mut ShmInfo) -> usize {
+    sys_call_3(SysCall::Shmctl, id, cmd, buf as usize)
+}
+
+/// 下面是系统调用的具体实现
+pub(crate) extern "C" fn task_shmget(
+    key: usize,
+    size: usize,
+    flags: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let flags = ShmFlags::from_bits_truncate(flags as u32);
+    let Some(size) = page_align_up(size) else {
+        return Errno::EINVAL.as_ret();
+    };
+
+    match shm_get(key, size as usize, flags) {
+        Ok(id) => id,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+pub(crate) extern "C" fn task_shmat(
+    id: usize,
+    addr: usize,
+    flags: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let flags = ShmFlags::from_bits_truncate(flags as u32);
+    let Some(size) = shm_size(id) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let size = size as u32;
+
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 指定了地址就必须挂载在这个地址
+    let Some(start) = find_user_range(vm_areas, addr, size, addr != 0) else {
+        return Errno::EINVAL.as_ret();
+    };
+
+    let mut page_flags = PTFlags::US | no_execute();
+    if !flags.contains(ShmFlags::RDONLY) {
+        page_flags |= PTFlags::RW;
+    }
+
+    let area = VmArea::new(start, start + size, page_flags, VmKind::Shm(id));
+    if !vm_areas.insert(area) {
+        return Errno::ENOMEM.as_ret();
+    }
+
+    if let Err(errno) = shm_attach(id, start, page_flags) {
+        vm_areas.remove_range(start, start + size);
+        return errno.as_ret();
+    }
+
+    start as usize
+}
+
+pub(crate) extern "C" fn task_shmdt(
+    addr: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let mut current = Task::current_task();
+    let vm_areas = unsafe { &mut current.as_mut().vm_areas };
+
+    // 必须是挂载的起始地址
+    let Some(&area) = vm_areas.find(addr as u32) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let VmKind::Shm(id) = area.kind else {
+        return Errno::EINVAL.as_ret();
+    };
+    if area.start as usize != addr {
+        return Errno::EINVAL.as_ret();
+    }
+
+    vm_areas.remove_range(area.start, area.end);
+    free_range(area.start, (area.end - area.start) as usize);
+    shm_detach(id);
+
+    0
+}
+
+pub(crate) extern "C" fn task_shmctl(
+    id: usize,
+    cmd: usize,
+    buf: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    let result = match cmd {
+        IPC_RMID => shm_remove(id),
+        IPC_STAT => shm_stat(id).and_then(|info| {
+            let bytes = unsafe {
+                slice::from_raw_parts(
+                    &info as *const ShmInfo as *const u8,
+                    size_of::<ShmInfo>(),
+                )
+            };
+            copy_to_user(buf, bytes)
+        }),
+        _ => Err(Errno::EINVAL),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(errno) => errno.as_ret(),
+    }
+}
@@ -1,5 +1,11 @@
-use crate::kernel::system_call::{sys_call, sys_call_1};
+use bitflags::bitflags;
+use core::hint::unreachable_unchecked;
+
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::system_call::{sys_call, sys_call_1, sys_call_3};
+use crate::kernel::tasks::exec::{copy_strings_from_user, execve, PATH_MAX};
 use crate::kernel::tasks::task::Task;
+use crate::mm::uaccess::{copy_to_user, strncpy_from_user};
 
 /// 系统调用枚举
 #[repr(C)]
@@ -8,22 +14,113 @@ pub enum SysCall {
     Write,
     Yield,
     Sleep,
+    Fork,
+    Brk,
+    Mmap,
+    Munmap,
+    Shmget,
+    Shmat,
+    Shmdt,
+    Shmctl,
+    Meminfo,
+    Mprotect,
+    Exit,
+    Waitpid,
+    Getpid,
+    Getppid,
+    Execve,
 }
 
+bitflags! {
+    /// waitpid的选项
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct WaitOptions: u32 {
+        /// 没有退出的子任务时立即返回0
+        const WNOHANG = 0x1;
+    }
+}
+
+#[inline(always)]
 pub fn sys_yield() {
     sys_call(SysCall::Yield);
 }
 
+#[inline(always)]
 pub fn sys_sleep(ms: usize) {
     sys_call_1(SysCall::Sleep, ms);
 }
 
+/// 复制当前任务,子任务返回0
+#[inline(always)]
+pub fn sys_fork() -> usize {
+    sys_call(SysCall::Fork)
+}
+
+/// 以code退出当前任务,不会返回
+#[inline(always)]
+pub fn sys_exit(code: i32) -> ! {
+    sys_call_1(SysCall::Exit, code as usize);
+    unsafe { unreachable_unchecked() }
+}
+
+/// 等待子任务退出,pid为-1时等待任意一个子任务,status不为空的时候写入退出码
+/// 返回子任务的pid,WNOHANG时没有退出的子任务返回0,失败返回错误码的相反数
+#[inline(always)]
+pub fn sys_waitpid(
+    pid: isize,
+    status: *mut i32,
+    options: WaitOptions,
+) -> usize {
+    sys_call_3(
+        SysCall::Waitpid,
+        pid as usize,
+        status as usize,
+        options.bits() as usize,
+    )
+}
+
+/// 等待任意一个子任务退出
+#[inline(always)]
+pub fn sys_wait(status: *mut i32) -> usize {
+    sys_waitpid(-1, status, WaitOptions::empty())
+}
+
+/// 当前任务的pid
+#[inline(always)]
+pub fn sys_getpid() -> usize {
+    sys_call(SysCall::Getpid)
+}
+
+/// 父任务的pid,没有父任务返回0
+#[inline(always)]
+pub fn sys_getppid() -> usize {
+    sys_call(SysCall::Getppid)
+}
+
+/// 执行path处的程序,argv和envp是以空指针结尾的字符串指针数组
+/// 成功不会返回,失败返回错误码的相反数
+#[inline(always)]
+pub fn sys_execve(
+    path: *const u8,
+    argv: *const *const u8,
+    envp: *const *const u8,
+) -> usize {
+    sys_call_3(SysCall::Execve, path as usize, argv as usize, envp as usize)
+}
+
+/// 用户任务的入口函数返回到这里,以0退出
+#[link_section = ".text.user"]
+pub(crate) fn user_task_return() -> ! {
+    sys_exit(0)
+}
+
 /// 下面是系统调用的具体实现
 pub(crate) extern "C" fn task_yield(
     _: usize,
     _: usize,
     _: usize,
     _: usize,
+    _: usize,
 ) -> usize {
     unsafe {
         Task::schedule();
@@ -36,7 +133,104 @@ pub(crate) extern "C" fn task_sleep(
     _: usize,
     _: usize,
     _: usize,
+    _: usize,
 ) -> usize {
     unsafe { Task::sleep(ms) }
     0
 }
+
+pub(crate) extern "C" fn task_fork(
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    unsafe { Task::fork() }
+}
+
+pub(crate) extern "C" fn task_exit(
+    code: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    unsafe { Task::exit(code as i32) }
+}
+
+pub(crate) extern "C" fn task_waitpid(
+    pid: usize,
+    status: usize,
+    options: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    // 没有进程组,pid只能是-1或者某个子任务
+    let pid = match pid as isize {
+        -1 => None,
+        pid if pid > 0 => Some(pid as u32),
+        _ => return Errno::EINVAL.as_ret(),
+    };
+    let options = WaitOptions::from_bits_truncate(options as u32);
+    let nohang = options.contains(WaitOptions::WNOHANG);
+
+    let (child, code) = match unsafe { Task::waitpid(pid, nohang) } {
+        Ok(Some(exited)) => exited,
+        Ok(None) => return 0,
+        Err(errno) => return errno.as_ret(),
+    };
+
+    if status != 0 {
+        if let Err(errno) = copy_to_user(status, &code.to_ne_bytes()) {
+            return errno.as_ret();
+        }
+    }
+    child as usize
+}
+
+pub(crate) extern "C" fn task_getpid(
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    unsafe { Task::current_task().as_ref().pid as usize }
+}
+
+pub(crate) extern "C" fn task_getppid(
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    unsafe { Task::current_task().as_ref().ppid() as usize }
+}
+
+pub(crate) extern "C" fn task_execve(
+    path: usize,
+    argv: usize,
+    envp: usize,
+    _: usize,
+    _: usize,
+) -> usize {
+    // 路径放在栈上,execve成功之后不会返回,堆上的内存就泄漏了
+    let mut buffer = [0u8; PATH_MAX];
+    let len = match strncpy_from_user(&mut buffer, path) {
+        Ok(len) if len < PATH_MAX => len,
+        Ok(_) => return Errno::ENAMETOOLONG.as_ret(),
+        Err(errno) => return errno.as_ret(),
+    };
+
+    let mut size = 0;
+    let args = copy_strings_from_user(argv, &mut size)
+        .and_then(|argv| Ok((argv, copy_strings_from_user(envp, &mut size)?)));
+    match args {
+        Ok((argv, envp)) => {
+            unsafe { execve(&buffer[..len], argv, envp) }.as_ret()
+        }
+        Err(errno) => errno.as_ret(),
+    }
+}
@@ -0,0 +1,321 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr;
+
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::system_call::memory::{page_align_up, stack_bottom};
+use crate::kernel::tasks::task::Task;
+use crate::libs::elf::{Elf, SegmentFlags, PT_INTERP, PT_LOAD};
+use crate::mm::frame::free_frame;
+use crate::mm::page::{
+    alloc_user_page, create_page_dir, destroy_page_dir, map_page,
+    switch_page_dir, with_frame, KERNEL_PAGE_DIR,
+};
+use crate::mm::paging::{no_execute, PTFlags, BASE_PAGE_SIZE};
+use crate::mm::shm::shm_detach;
+use crate::mm::uaccess::{copy_from_user, copy_to_user, strncpy_from_user};
+use crate::mm::vma::{
+    MmapProt, VmArea, VmAreas, VmKind, USER_STACK_SIZE, USER_STACK_TOP,
+};
+
+/// 路径的最大长度,包括结尾的0
+pub const PATH_MAX: usize = 256;
+/// 参数或者环境变量的最大数量
+const MAX_ARG_COUNT: usize = 32;
+/// 参数和环境变量字符串的总长度,包括结尾的0
+const MAX_ARG_SIZE: usize = BASE_PAGE_SIZE;
+
+/// 辅助向量的类型,告诉程序装载的信息
+const AT_NULL: u32 = 0;
+const AT_PHDR: u32 = 3;
+const AT_PHENT: u32 = 4;
+const AT_PHNUM: u32 = 5;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+const AT_UID: u32 = 11;
+
+/// 还没有文件系统,用户程序由build.rs编译之后嵌入内核镜像,按路径查找
+static PROGRAMS: [(&[u8], &[u8]); 1] = [(
+    b"/bin/hello",
+    include_bytes!(concat!(env!("OUT_DIR"), "/hello")),
+)];
+
+fn find_program(path: &[u8]) -> Option<&'static [u8]> {
+    PROGRAMS
+        .iter()
+        .find(|(name, _)| *name == path)
+        .map(|(_, image)| *image)
+}
+
+/// 从用户空间拷贝以空指针结尾的字符串指针数组,size累计字符串的总长度
+/// addr为0时当作空数组
+pub(crate) fn copy_strings_from_user(
+    addr: usize,
+    size: &mut usize,
+) -> Result<Vec<Vec<u8>>, Errno> {
+    let mut strings = Vec::new();
+    if addr == 0 {
+        return Ok(strings);
+    }
+
+    loop {
+        let mut ptr = [0u8; size_of::<u32>()];
+        let offset = strings.len() * size_of::<u32>();
+        copy_from_user(
+            &mut ptr,
+            addr.checked_add(offset).ok_or(Errno::EFAULT)?,
+        )?;
+        let ptr = u32::from_ne_bytes(ptr) as usize;
+        if ptr == 0 {
+            return Ok(strings);
+        }
+        if strings.len() == MAX_ARG_COUNT {
+            return Err(Errno::E2BIG);
+        }
+
+        let mut string = vec![0u8; MAX_ARG_SIZE - *size];
+        let len = strncpy_from_user(&mut string, ptr)?;
+        // 放不下结尾的0
+        if len == string.len() {
+            return Err(Errno::E2BIG);
+        }
+        string.truncate(len);
+        string.shrink_to_fit();
+
+        *size += len + 1;
+        strings.push(string);
+    }
+}
+
+/// 把ELF文件的段装载到当前的地址空间,段的区域登记到vm_areas
+/// 返回程序头表在内存中的地址,没有装载返回0
+fn load_segments(elf: &Elf, vm_areas: &mut VmAreas) -> Result<u32, Errno> {
+    let header = &elf.header;
+    let phdr_size = header.phnum as u32 * header.phentsize as u32;
+    let mut phdr = 0;
+    let mut brk = 0;
+    let mut executable = false;
+
+    for segment in elf.program_headers() {
+        match segment.p_type {
+            PT_LOAD if segment.memsz != 0 => {}
+            // 只支持静态链接的程序
+            PT_INTERP => return Err(Errno::ENOEXEC),
+            _ => continue,
+        }
+
+        let data = elf.segment_data(&segment).ok_or(Errno::ENOEXEC)?;
+        // 第0页留给空指针,段不能和栈重叠
+        let start = segment.vaddr & !(BASE_PAGE_SIZE as u32 - 1);
+        let end = (segment.vaddr as usize)
+            .checked_add(segment.memsz as usize)
+            .and_then(page_align_up)
+            .ok_or(Errno::ENOEXEC)?;
+        if start < BASE_PAGE_SIZE as u32
+            || end > stack_bottom()
+            || vm_areas.overlapped(start, end).count() != 0
+        {
+            return Err(Errno::ENOEXEC);
+        }
+
+        let segment_flags = segment.flags();
+        let mut prot = MmapProt::empty();
+        prot.set(MmapProt::READ, segment_flags.contains(SegmentFlags::READ));
+        prot.set(MmapProt::WRITE, segment_flags.contains(SegmentFlags::WRITE));
+        prot.set(MmapProt::EXEC, segment_flags.contains(SegmentFlags::EXEC));
+        let flags = prot.page_flags();
+
+        // 段是私有的,和私有的匿名映射一样fork之后写时复制
+        if !vm_areas.insert(VmArea::new(start, end, flags, VmKind::Anonymous)) {
+            return Err(Errno::ENOMEM);
+        }
+
+        // 立即分配物理页,拷贝文件中的内容,剩下的部分清零
+        let file_end = segment.vaddr + segment.filesz;
+        for page in (start..end).step_by(BASE_PAGE_SIZE) {
+            let frame = alloc_user_page().ok_or(Errno::ENOMEM)?;
+            with_frame(frame, 0, |dst| unsafe {
+                ptr::write_bytes(dst, 0, BASE_PAGE_SIZE);
+
+                let low = page.max(segment.vaddr);
+                let high = (page + BASE_PAGE_SIZE as u32).min(file_end);
+                if low < high {
+                    let src = &data[(low - segment.vaddr) as usize..]
+                        [..(high - low) as usize];
+                    ptr::copy_nonoverlapping(
+                        src.as_ptr(),
+                        dst.add((low - page) as usize),
+                        src.len(),
+                    );
+                }
+            });
+
+            if !map_page(page, frame, flags) {
+                free_frame(frame);
+                return Err(Errno::ENOMEM);
+            }
+        }
+
+        // 入口要在装载的可执行的段中
+        let segment_end = segment.vaddr + segment.memsz;
+        if segment_flags.contains(SegmentFlags::EXEC)
+            && (segment.vaddr..segment_end).contains(&header.entry)
+        {
+            executable = true;
+        }
+
+        // 程序头表在这个段中
+        if segment.offset <= header.phoff
+            && header.phoff + phdr_size <= segment.offset + segment.filesz
+        {
+            phdr = segment.vaddr + (header.phoff - segment.offset);
+        }
+        brk = brk.max(end);
+    }
+
+    if !executable {
+        return Err(Errno::ENOEXEC);
+    }
+
+    // 堆紧跟在最后一个段的后面,栈在用户空间的顶端
+    let flags = PTFlags::RW | PTFlags::US | no_execute();
+    let registered =
+        vm_areas.insert(VmArea::new(brk, brk, flags, VmKind::Heap))
+            && vm_areas.insert(VmArea::new(
+                USER_STACK_TOP - USER_STACK_SIZE,
+                USER_STACK_TOP,
+                flags,
+                VmKind::Stack,
+            ));
+    if !registered {
+        return Err(Errno::ENOMEM);
+    }
+
+    Ok(phdr)
+}
+
+/// 在用户栈上放好参数、环境变量和辅助向量,返回栈顶
+/// 从栈顶开始依次是argc、argv、0、envp、0、辅助向量,字符串放在栈的最高处
+fn setup_stack(
+    argv: &[Vec<u8>],
+    envp: &[Vec<u8>],
+    auxv: &[(u32, u32)],
+) -> Result<u32, Errno> {
+    let strings_size = argv
+        .iter()
+        .chain(envp)
+        .map(|string| string.len() + 1)
+        .sum::<usize>();
+    let word_count = 1 + argv.len() + 1 + envp.len() + 1 + auxv.len() * 2;
+
+    let strings = USER_STACK_TOP - strings_size as u32;
+    // 栈顶按16字节对齐
+    let esp = (strings - (word_count * size_of::<u32>()) as u32) & !0xf;
+    let mut stack = vec![0u8; (USER_STACK_TOP - esp) as usize];
+
+    let mut words = Vec::with_capacity(word_count);
+    words.push(argv.len() as u32);
+    let mut addr = strings;
+    for group in [argv, envp] {
+        for string in group {
+            let offset = (addr - esp) as usize;
+            stack[offset..offset + string.len()].copy_from_slice(string);
+            words.push(addr);
+            addr += string.len() as u32 + 1;
+        }
+        words.push(0);
+    }
+    auxv.iter()
+        .for_each(|&(key, value)| words.extend([key, value]));
+
+    for (index, word) in words.iter().enumerate() {
+        let offset = index * size_of::<u32>();
+        stack[offset..offset + size_of::<u32>()]
+            .copy_from_slice(&word.to_ne_bytes());
+    }
+
+    copy_to_user(esp as usize, &stack)?;
+    Ok(esp)
+}
+
+/// 在新的地址空间中装载程序并放好用户栈,返回栈顶
+fn load(
+    elf: &Elf,
+    vm_areas: &mut VmAreas,
+    argv: &[Vec<u8>],
+    envp: &[Vec<u8>],
+    uid: u32,
+) -> Result<u32, Errno> {
+    let phdr = load_segments(elf, vm_areas)?;
+
+    let header = &elf.header;
+    let mut auxv = vec![
+        (AT_PHENT, header.phentsize as u32),
+        (AT_PHNUM, header.phnum as u32),
+        (AT_PAGESZ, BASE_PAGE_SIZE as u32),
+        (AT_ENTRY, header.entry),
+        (AT_UID, uid),
+    ];
+    if phdr != 0 {
+        auxv.push((AT_PHDR, phdr));
+    }
+    auxv.push((AT_NULL, 0));
+
+    setup_stack(argv, envp, &auxv)
+}
+
+/// 用path处的程序替换当前任务的地址空间,从程序的入口进入用户态
+/// 成功不会返回,失败的时候原来的地址空间不变,返回错误码
+pub unsafe fn execve(
+    path: &[u8],
+    argv: Vec<Vec<u8>>,
+    envp: Vec<Vec<u8>>,
+) -> Errno {
+    let Some(image) = find_program(path) else {
+        return Errno::ENOENT;
+    };
+    let Some(elf) = Elf::parse(image) else {
+        return Errno::ENOEXEC;
+    };
+    let Some(pde) = create_page_dir() else {
+        return Errno::ENOMEM;
+    };
+
+    // 先在新的地址空间中装载,失败的时候还可以回到原来的地址空间
+    let mut task = Task::current_task();
+    let old_pde = task.as_ref().pde;
+    let old_areas = task.as_ref().vm_areas;
+    task.as_mut().pde = pde;
+    task.as_mut().vm_areas = VmAreas::new();
+    switch_page_dir(pde);
+
+    let uid = task.as_ref().uid;
+    let vm_areas = &mut task.as_mut().vm_areas;
+    let esp = match load(&elf, vm_areas, &argv, &envp, uid) {
+        Ok(esp) => esp,
+        Err(errno) => {
+            task.as_mut().pde = old_pde;
+            task.as_mut().vm_areas = old_areas;
+            switch_page_dir(old_pde);
+            destroy_page_dir(pde);
+            return errno;
+        }
+    };
+
+    // 不会再失败了,释放原来的地址空间
+    old_areas.iter().for_each(|area| {
+        if let VmKind::Shm(id) = area.kind {
+            shm_detach(id);
+        }
+    });
+    if old_pde != KERNEL_PAGE_DIR {
+        destroy_page_dir(old_pde);
+    }
+
+    // 进入用户态之后不会返回,参数要在这里释放
+    let entry = elf.header.entry;
+    drop(argv);
+    drop(envp);
+    Task::enter_user_mode(entry, esp)
+}
@@ -1,25 +1,40 @@
 use crate::kernel::sync::mutex::Mutex;
 use core::ptr::Unique;
 
+use crate::kernel::tasks::run_queue::RunQueue;
+use crate::kernel::tasks::table::TaskTable;
 use crate::kernel::tasks::task::Task;
 use crate::kernel::tasks::thread::idle::idle;
 use crate::kernel::tasks::thread::init::init;
 use crate::libs::kernel_linked_list::LinkedList;
+use crate::mm::page::{KERNEL_BASE, KERNEL_LOAD_ADDRESS, KERNEL_PAGE_DIR};
+use crate::mm::paging::BASE_PAGE_SIZE;
 use crate::KERNEL_MAGIC;
 
+pub mod exec;
+pub mod run_queue;
+mod table;
 pub mod task;
 mod thread;
 
-/// 任务数量
-const TASKS_NUMBER: usize = 64;
-/// 任务列表
-static TASKS: Mutex<[Option<Unique<Task>>; TASKS_NUMBER]> =
-    Mutex::new([None; TASKS_NUMBER]);
+/// 任务表,按pid查找,idle的pid是0,init的pid是1
+static TASKS: Mutex<TaskTable> = Mutex::new(TaskTable::new());
+/// 就绪队列,只在关闭中断的时候访问
+static mut RUN_QUEUE: RunQueue = RunQueue::new();
+/// idle任务的pid,idle不在就绪队列中
+const IDLE_PID: u32 = 0;
 /// 默认的阻塞队列
 static mut DEFAULT_BLOCK_LINKED_LIST: LinkedList<()> = LinkedList::new();
 
 /// IDLE任务指针
 static mut IDLE_TASK: Unique<Task> = Unique::dangling();
+/// init任务指针,收养父任务已经退出的任务
+static mut INIT_TASK: Unique<Task> = Unique::dangling();
+
+/// 当前任务,一开始是引导任务,PCB在启动栈所在的页
+static mut CURRENT_TASK: *mut Task = ((KERNEL_BASE + KERNEL_LOAD_ADDRESS)
+    as usize
+    - BASE_PAGE_SIZE) as *mut Task;
 
 /// 内核用户
 const KERNEL_USER: u32 = 0;
@@ -31,8 +46,10 @@ static SLEEP_TASK_LIST: Mutex<LinkedList<()>> = Mutex::new(LinkedList::new());
 
 unsafe fn task_setup() {
     let mut current = Task::current_task();
+    current.as_mut().kernel_stack = KERNEL_BASE + KERNEL_LOAD_ADDRESS;
     current.as_mut().magic_number = KERNEL_MAGIC;
     current.as_mut().ticks = 1;
+    current.as_mut().pde = KERNEL_PAGE_DIR;
 }
 
 pub fn init_task() {
@@ -40,7 +57,9 @@ pub fn init_task() {
         // 初始化0x10000的的任务
         task_setup();
         // idle任务优先级为1,永远不会被调度,除非没有就绪任务
-        IDLE_TASK = Task::create(idle, "idle", 1, KERNEL_USER);
-        Task::create(init, "init", 5, NORMAL_USER);
+        IDLE_TASK = Task::create(idle, "idle", 1, KERNEL_USER)
+            .expect("no memory for idle task");
+        INIT_TASK = Task::create(init, "init", 5, NORMAL_USER)
+            .expect("no memory for init task");
     }
 }
@@ -0,0 +1,75 @@
+use core::ptr::{NonNull, Unique};
+
+use crate::kernel::interrupts::if_enabled;
+use crate::kernel::tasks::task::Task;
+use crate::libs::kernel_linked_list::LinkedList;
+
+/// 多级反馈队列的级数,第0级最高
+pub const LEVELS: usize = 4;
+/// 每隔这么多个时间片把所有就绪的任务提升到第0级,防止低级的任务饿死
+/// 必须是2的幂
+pub const BOOST_INTERVAL: u64 = 128;
+
+/// 就绪队列,每一级一个队列,同一级的任务轮流执行
+/// 任务通过PCB中的node挂在队列上,就绪的任务不会同时在阻塞或者睡眠队列中
+/// 只在关闭中断的时候访问
+pub struct RunQueue {
+    queues: [LinkedList<()>; LEVELS],
+    /// 第i位表示第i级的队列不为空
+    bitmap: u32,
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        RunQueue {
+            queues: [const { LinkedList::new() }; LEVELS],
+            bitmap: 0,
+        }
+    }
+
+    /// 任务加入所在级别的队尾
+    pub unsafe fn push(&mut self, task: NonNull<Task>) {
+        assert!(!if_enabled());
+        assert!(task.as_ref().node.next.is_none());
+        assert!(task.as_ref().node.prev.is_none());
+
+        let level = task.as_ref().level as usize;
+        self.queues[level]
+            .push_back_node(Unique::from(NonNull::from(&task.as_ref().node)));
+        self.bitmap |= 1 << level;
+    }
+
+    /// 取出最高一级的队首任务,没有就绪的任务返回None
+    pub unsafe fn pop(&mut self) -> Option<NonNull<Task>> {
+        assert!(!if_enabled());
+        if self.bitmap == 0 {
+            return None;
+        }
+
+        // 最低的置位就是最高的非空级别
+        let level = self.bitmap.trailing_zeros() as usize;
+        let queue = &mut self.queues[level];
+        let node = queue.front_node()?;
+        queue.unlink_node(node);
+        if queue.is_empty() {
+            self.bitmap &= !(1 << level);
+        }
+
+        Task::get_task(node)
+    }
+
+    /// 把所有的任务提升到第0级,保持原来的先后顺序
+    pub unsafe fn boost(&mut self) {
+        assert!(!if_enabled());
+        for level in 1..LEVELS {
+            while let Some(node) = self.queues[level].front_node() {
+                self.queues[level].unlink_node(node);
+                let mut task = Task::get_task(node).unwrap();
+                task.as_mut().level = 0;
+                self.queues[0].push_back_node(Unique::from(node));
+            }
+        }
+
+        self.bitmap = !self.queues[0].is_empty() as u32;
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+use core::ptr::Unique;
+
+use crate::kernel::tasks::task::Task;
+
+/// 最多的任务数量
+pub const TASKS_MAX: usize = 1024;
+/// pid的上限,分配到上限之后从头查找没有使用的pid
+const PID_MAX: u32 = 32768;
+/// 回绕之后从这里开始分配,idle和init的pid不会被复用
+const PID_WRAP: u32 = 2;
+/// 第一次登记任务时桶的数量,必须是2的幂
+const INITIAL_BUCKETS: usize = 16;
+
+/// 任务表,按pid散列到桶中,桶的数量随着任务的数量翻倍
+/// pid是连续分配的,散列之后分布很均匀,查找是O(1)的
+pub struct TaskTable {
+    buckets: Vec<Vec<(u32, Unique<Task>)>>,
+    len: usize,
+    next_pid: u32,
+}
+
+impl TaskTable {
+    pub const fn new() -> Self {
+        TaskTable {
+            buckets: Vec::new(),
+            len: 0,
+            next_pid: 0,
+        }
+    }
+
+    fn bucket(&self, pid: u32) -> usize {
+        pid as usize & (self.buckets.len() - 1)
+    }
+
+    /// 查找编号为pid的任务
+    pub fn get(&self, pid: u32) -> Option<Unique<Task>> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        self.buckets[self.bucket(pid)]
+            .iter()
+            .find(|(id, _)| *id == pid)
+            .map(|(_, task)| *task)
+    }
+
+    /// 为任务分配一个没有使用的pid并登记,返回分配的pid
+    /// 任务数量到达上限或者没有内存返回None
+    pub fn insert(&mut self, task: Unique<Task>) -> Option<u32> {
+        if self.len >= TASKS_MAX {
+            return None;
+        }
+        // 扩容失败还可以继续用原来的桶,只是链更长
+        if self.len >= self.buckets.len() {
+            self.grow();
+        }
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let pid = self.alloc_pid();
+        let index = self.bucket(pid);
+        let bucket = &mut self.buckets[index];
+        bucket.try_reserve(1).ok()?;
+        bucket.push((pid, task));
+
+        self.len += 1;
+        Some(pid)
+    }
+
+    /// 移出编号为pid的任务
+    pub fn remove(&mut self, pid: u32) -> Option<Unique<Task>> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let index = self.bucket(pid);
+        let bucket = &mut self.buckets[index];
+        let position = bucket.iter().position(|(id, _)| *id == pid)?;
+        self.len -= 1;
+        Some(bucket.swap_remove(position).1)
+    }
+
+    /// 所有登记的任务,顺序和pid无关
+    pub fn iter(&self) -> impl Iterator<Item = Unique<Task>> + '_ {
+        self.buckets.iter().flatten().map(|(_, task)| *task)
+    }
+
+    /// 下一个pid,到达上限之后回绕,跳过正在使用的pid
+    /// 任务数量小于pid的数量,总能找到
+    fn alloc_pid(&mut self) -> u32 {
+        loop {
+            let pid = self.next_pid;
+            self.next_pid = if pid + 1 >= PID_MAX {
+                PID_WRAP
+            } else {
+                pid + 1
+            };
+            if self.get(pid).is_none() {
+                return pid;
+            }
+        }
+    }
+
+    /// 桶的数量翻倍,重新散列所有的任务,没有内存的时候什么也不做
+    fn grow(&mut self) {
+        let count = (self.buckets.len() * 2).max(INITIAL_BUCKETS);
+        let mut buckets = Vec::new();
+        if buckets.try_reserve_exact(count).is_err() {
+            return;
+        }
+        buckets.resize_with(count, Vec::new);
+
+        // 先给每个桶预留好空间,重新散列的时候就不会失败了
+        let mut sizes = Vec::new();
+        if sizes.try_reserve_exact(count).is_err() {
+            return;
+        }
+        sizes.resize(count, 0);
+        for (pid, _) in self.buckets.iter().flatten() {
+            sizes[*pid as usize & (count - 1)] += 1;
+        }
+        for (bucket, size) in buckets.iter_mut().zip(sizes) {
+            if bucket.try_reserve_exact(size).is_err() {
+                return;
+            }
+        }
+
+        for (pid, task) in self.buckets.drain(..).flatten() {
+            buckets[pid as usize & (count - 1)].push((pid, task));
+        }
+        self.buckets = buckets;
+    }
+}
@@ -1,44 +1,70 @@
 //! Rust x86 use System V ABI default
 //! caller saved eax, ecx, edx
 //! callee saved ebx, esi, edi, ebp, esp
-use alloc::alloc::alloc;
-use core::alloc::Layout;
+use core::alloc::{Allocator, Layout};
 use core::arch::asm;
 use core::fmt::{Display, Formatter};
+use core::iter;
 use core::mem::size_of;
 use core::ptr::{NonNull, Unique};
 use core::{mem, ptr};
 
 use crate::kernel::global::{TSS, USER_CODE_SELECTOR, USER_DATA_SELECTOR};
-use x86::bits32::paging::BASE_PAGE_SIZE;
 
 use crate::kernel::interrupts::clock::{JIFFIES, JIFFY};
 use crate::kernel::interrupts::handler_entry::interrupt_exit;
-use crate::kernel::interrupts::{if_enabled, without_interrupt};
+use crate::kernel::interrupts::{
+    enable_interrupt, if_enabled, without_interrupt,
+};
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::system_call::sys_call::user_task_return;
+use crate::kernel::tasks::run_queue::LEVELS;
 use crate::kernel::tasks::{
-    DEFAULT_BLOCK_LINKED_LIST, IDLE_TASK, KERNEL_USER, SLEEP_TASK_LIST, TASKS,
-    TASKS_NUMBER,
+    CURRENT_TASK, DEFAULT_BLOCK_LINKED_LIST, IDLE_PID, IDLE_TASK, INIT_TASK,
+    KERNEL_USER, RUN_QUEUE, SLEEP_TASK_LIST, TASKS,
 };
 use crate::libs::kernel_linked_list::{LinkedList, Node};
-use crate::mm::page::KERNEL_PAGE_DIR;
+use crate::mm::kernel_stack::{
+    alloc_kernel_stack, free_kernel_stack, kernel_stack_high_water,
+};
+use crate::mm::page::{
+    create_page_dir, destroy_page_dir, fork_page_dir, map_user_text,
+    switch_page_dir, user_text_size, KERNEL_PAGE_DIR,
+};
+use crate::mm::paging::{no_execute, PTFlags};
+use crate::mm::shm::{shm_detach, shm_fork};
+use crate::mm::slab::SlabAllocator;
+use crate::mm::uaccess::copy_to_user;
+use crate::mm::vma::{
+    VmArea, VmAreas, VmKind, USER_HEAP_BASE, USER_STACK_SIZE, USER_STACK_TOP,
+    USER_TEXT_BASE,
+};
 use crate::KERNEL_MAGIC;
 
-type TargetFn = fn() -> !;
+/// 任务的入口函数,返回之后任务以0退出
+type TargetFn = fn();
+
+/// 被杀死的任务的退出码
+pub const KILLED_EXIT_CODE: i32 = -1;
 
-/// 任务,用一页表示一个任务,用栈底信息(页开始的地方表示这个任务)
-/// 按照4096个字节对齐, PCB处于低地址
+/// 任务,PCB占用一页,按照4096个字节对齐
+/// 内核栈单独分配,栈的下面是不映射的保护页
 #[repr(C)]
 #[repr(align(4096))]
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Task {
-    // 内核栈地址
+    // 内核栈地址,任务切换时保存的esp
     pub stack: u32,
+    // 内核栈的栈顶
+    pub kernel_stack: u32,
     // 阻塞队列
     pub node: Node<()>,
     // 任务状态
     pub state: TaskState,
     // 优先级
     pub priority: u32,
+    // 多级反馈队列的级别,0最高,级别越低时间片越长
+    pub level: u32,
     // 剩余时间片
     pub ticks: u64,
     // 上次执行时全局时间片
@@ -49,17 +75,34 @@ pub struct Task {
     pub uid: u32,
     // 页目录物理地址
     pub pde: u32,
+    // 用户虚拟内存区域
+    pub vm_areas: VmAreas,
+    // 被OOM杀死,回到用户态的时候退出
+    pub killed: bool,
+    // 任务编号
+    pub pid: u32,
+    // 父任务,退出之后由父任务回收,没有父任务的时候退出之后直接回收
+    pub parent: Option<NonNull<Task>>,
+    // 第一个子任务
+    pub children: Option<NonNull<Task>>,
+    // 父任务的下一个子任务
+    pub sibling: Option<NonNull<Task>>,
+    // 退出码
+    pub exit_code: i32,
     // 魔数
     pub magic_number: u32,
 }
 
+/// PCB中有指向其他任务的指针,只在关闭中断的时候修改,可以放在任务表中
+unsafe impl Send for Task {}
+
 /// 任务上下文,切换前保存,切换后恢复
 pub struct TaskFrame {
     edi: u32,
     esi: u32,
     ebx: u32,
     ebp: u32,
-    eip: Option<TargetFn>,
+    eip: u32,
 }
 
 /// 中断帧,进入用户模式是以模拟中断返回的方式进行的
@@ -97,6 +140,8 @@ pub enum TaskState {
     TaskBlocked,
     TaskSleep,
     TaskWaiting,
+    /// 已经退出,等待父任务回收
+    TaskZombie,
     TaskDied,
 }
 
@@ -109,42 +154,66 @@ impl Display for TaskState {
             TaskState::TaskBlocked => f.write_str("Blocked"),
             TaskState::TaskSleep => f.write_str("Sleep"),
             TaskState::TaskWaiting => f.write_str("Waiting"),
+            TaskState::TaskZombie => f.write_str("Zombie"),
             TaskState::TaskDied => f.write_str("Died"),
         }
     }
 }
 
 impl Task {
+    /// 创建任务,没有内存返回None
     pub fn create(
         target: TargetFn,
         name: &'static str,
         priority: u32,
         uid: u32,
-    ) -> Unique<Task> {
-        // 计算栈顶地址,栈从高地址向低地址增长
-        // 所以加上BASE_PAGE_SIZE来计算栈顶
-        let mut task = Task::get_free_task();
+    ) -> Option<Unique<Task>> {
+        // 用户任务有自己的页目录
+        let pde = if uid == KERNEL_USER {
+            KERNEL_PAGE_DIR
+        } else {
+            create_page_dir()?
+        };
+        let Some(mut task) = Task::get_free_task() else {
+            if pde != KERNEL_PAGE_DIR {
+                destroy_page_dir(pde);
+            }
+            return None;
+        };
+
+        // 任务上下文放在内核栈的栈顶
         let mut task_frame = Task::get_task_frame(task);
 
         let task_mut = unsafe { task_frame.as_mut() };
-        task_mut.ebx = 0x11111111;
+        // 从task_entry进入任务函数,ebx中是任务函数
+        task_mut.ebx = target as usize as u32;
         task_mut.esi = 0x22222222;
         task_mut.edi = 0x33333333;
         task_mut.ebp = 0x44444444;
-        task_mut.eip = Some(target);
+        task_mut.eip = task_entry as usize as u32;
 
         let task_mut = unsafe { task.as_mut() };
         task_mut.name = name;
         task_mut.priority = priority;
+        task_mut.level = 0;
+        task_mut.ticks = task_mut.time_slice();
         task_mut.uid = uid;
         task_mut.jiffies = 0;
-        task_mut.state = TaskState::TaskReady;
         task_mut.magic_number = KERNEL_MAGIC;
-        task_mut.pde = KERNEL_PAGE_DIR;
+        task_mut.pde = pde;
+        task_mut.vm_areas = VmAreas::new();
+        task_mut.killed = false;
+        task_mut.parent = None;
+        task_mut.children = None;
+        task_mut.sibling = None;
+        task_mut.exit_code = 0;
         // 内核栈
         task_mut.stack = task_frame.as_ptr() as u32;
 
-        task
+        unsafe {
+            Task::make_ready(NonNull::from(task));
+        }
+        Some(task)
     }
 
     pub const fn from_ptr(raw_ptr: usize) -> *mut Task {
@@ -155,19 +224,8 @@ impl Task {
     }
 
     pub fn current_task() -> NonNull<Task> {
-        let current: *mut Task;
-        // 栈是在页内,因此只需要用sp的值,就能知道栈在哪一页
-        // 就能知道是哪个任务
-        unsafe {
-            asm!(
-                "movl %esp, %eax",
-                "andl $0xfffff000, %eax",
-                out("eax") current,
-                options(att_syntax)
-            );
-
-            NonNull::new_unchecked(current)
-        }
+        // 内核栈和PCB分开了,不能再通过esp计算当前任务
+        unsafe { NonNull::new_unchecked(CURRENT_TASK) }
     }
 
     pub unsafe fn schedule() {
@@ -175,13 +233,14 @@ impl Task {
         assert!(!if_enabled());
 
         let mut current = Task::current_task();
-        // 查找就绪的任务
-        let next = Task::task_search(TaskState::TaskReady);
 
-        // 不能是默认值
-        assert!(next.is_some(), "next task can not be null");
+        // 修改当前任务从Running -> Ready,排到同一级的队尾
+        if current.as_mut().state == TaskState::TaskRunning {
+            Task::make_ready(current);
+        }
 
-        let mut next = next.unwrap();
+        // 最高一级的队首任务,没有就绪任务则切换到idle任务
+        let mut next = RUN_QUEUE.pop().map_or(IDLE_TASK, Unique::from);
         assert_eq!(
             next.as_ref().magic_number,
             KERNEL_MAGIC,
@@ -189,9 +248,12 @@ impl Task {
             next
         );
 
-        // 修改当前任务从Running -> Ready
-        if current.as_mut().state == TaskState::TaskRunning {
-            current.as_mut().state = TaskState::TaskReady
+        // 任务已经退出,回收它的地址空间
+        if matches!(
+            current.as_ref().state,
+            TaskState::TaskZombie | TaskState::TaskDied
+        ) {
+            Task::release_page_dir(current);
         }
 
         next.as_mut().state = TaskState::TaskRunning;
@@ -201,65 +263,68 @@ impl Task {
         }
 
         Task::task_activate(next);
-        task_switch(next.as_ptr());
+        CURRENT_TASK = next.as_ptr();
+        task_switch(current.as_ptr(), next.as_ptr());
     }
 
-    pub fn get_free_task() -> Unique<Task> {
-        let task_layout =
-            Layout::from_size_align(size_of::<Task>(), BASE_PAGE_SIZE)
-                .expect("init task error");
+    /// PCB从slab分配器的页大小缓存中分配,并分配带保护页的内核栈
+    /// 任务登记到任务表中并分配pid,没有内存或者任务数量到达上限返回None
+    pub fn get_free_task() -> Option<Unique<Task>> {
+        let mut free_task = Unique::from(
+            SlabAllocator
+                .allocate(Layout::new::<Task>())
+                .ok()?
+                .cast::<Task>(),
+        );
+        let pcb = free_task.as_non_null_ptr().cast::<u8>();
+        let free_pcb =
+            || unsafe { SlabAllocator.deallocate(pcb, Layout::new::<Task>()) };
 
-        let free_task =
-            unsafe { Unique::new_unchecked(alloc(task_layout) as *mut Task) };
+        let Some(kernel_stack) = alloc_kernel_stack() else {
+            free_pcb();
+            return None;
+        };
+        unsafe {
+            free_task.as_mut().kernel_stack = kernel_stack;
+        }
 
-        let pos = TASKS.lock().iter().position(Option::is_none);
+        // 先回收没有父任务的已经退出的任务,腾出任务表
+        Task::reap_died();
+        let pid = without_interrupt(|| TASKS.lock().insert(free_task));
 
-        // 不能写在一行,垃圾spin.lock会造成死锁
-        if let Some(index) = pos {
-            TASKS.lock()[index] = Some(free_task);
+        let Some(pid) = pid else {
+            free_kernel_stack(kernel_stack);
+            free_pcb();
+            return None;
         };
+        unsafe {
+            free_task.as_mut().pid = pid;
+        }
 
-        free_task
+        Some(free_task)
     }
 
-    pub unsafe fn task_search(state: TaskState) -> Option<Unique<Task>> {
-        let mut result = None;
-        without_interrupt(|| {
-            let current_task = Task::current_task();
-
-            (0..TASKS_NUMBER).for_each(|index| {
-                let task = TASKS.lock()[index];
-
-                if let Some(task) = task {
-                    if task.as_ptr() == current_task.as_ptr() {
-                        return;
-                    }
-
-                    if task.as_ref().state != state {
-                        return;
-                    }
-
-                    if result.is_none()
-                        || result.is_some_and(|res_task: Unique<Task>| {
-                            res_task.as_ref().ticks < task.as_ref().ticks
-                                || task.as_ref().jiffies
-                                    < res_task.as_ref().jiffies
-                        })
-                    {
-                        result = Some(task);
-                    }
-                };
-            });
-
-            // 没有就绪任务,则切换到idle任务
-            if result.is_none() {
-                result = Some(IDLE_TASK);
-            }
+    /// 按pid查找任务
+    pub fn find(pid: u32) -> Option<NonNull<Task>> {
+        without_interrupt(|| TASKS.lock().get(pid)).map(NonNull::from)
+    }
 
-            result
+    /// 任务表中pid不小于pid的任务中pid最小的那个
+    pub fn next_task(pid: u32) -> Option<Unique<Task>> {
+        without_interrupt(|| {
+            TASKS
+                .lock()
+                .iter()
+                .filter(|task| unsafe { task.as_ref().pid } >= pid)
+                .min_by_key(|task| unsafe { task.as_ref().pid })
         })
     }
 
+    /// 在持有任务表的锁的时候访问所有的任务,f中不能再获取任务表的锁
+    pub fn for_each_task(f: impl FnMut(Unique<Task>)) {
+        without_interrupt(|| TASKS.lock().iter().for_each(f));
+    }
+
     pub unsafe fn block(
         mut task: NonNull<Task>,
         state: TaskState,
@@ -306,7 +371,7 @@ impl Task {
         }
 
         // shadow
-        let mut task = task.unwrap();
+        let task = task.unwrap();
 
         // 必须保证不可中断
         assert!(!if_enabled());
@@ -327,7 +392,7 @@ impl Task {
         assert!(task.as_ref().node.prev.is_none());
 
         // 改为就绪状态
-        task.as_mut().state = TaskState::TaskReady;
+        Task::make_ready(task);
     }
 
     pub unsafe fn sleep(ms: usize) {
@@ -379,6 +444,20 @@ impl Task {
         Task::schedule();
     }
 
+    /// 提前唤醒睡眠中的任务,任务不在睡眠的时候什么也不做
+    pub unsafe fn wake(mut task: NonNull<Task>) {
+        assert!(!if_enabled());
+        if task.as_ref().state != TaskState::TaskSleep {
+            return;
+        }
+
+        SLEEP_TASK_LIST
+            .lock()
+            .unlink_node(NonNull::from(&task.as_ref().node));
+        task.as_mut().ticks = task.as_ref().time_slice();
+        Task::make_ready(task);
+    }
+
     // 唤醒所有睡觉的任务
     pub unsafe fn wake_up() {
         assert!(!if_enabled());
@@ -396,23 +475,66 @@ impl Task {
                 current_node = node.as_mut().next;
 
                 // 再将节点移出队列
-                task.as_mut().ticks = task.as_ref().priority as u64;
+                task.as_mut().ticks = task.as_ref().time_slice();
                 SLEEP_TASK_LIST.lock().unlink_node(node);
                 // 确保移出队列
                 node.as_mut().next = None;
                 node.as_mut().prev = None;
 
                 // 改为就绪状态
-                task.as_mut().state = TaskState::TaskReady;
+                Task::make_ready(task);
             }
         }
     }
 
     /// 返回用户模式,模拟中断返回
-    pub unsafe fn task_to_user_mode(target: TargetFn) {
-        let task = Task::current_task();
+    /// 成功不会返回,没有内存映射用户代码段或者登记区域返回错误码
+    pub unsafe fn task_to_user_mode(target: TargetFn) -> Errno {
+        let mut task = Task::current_task();
+
+        // 登记用户代码段、用户栈和用户堆,栈和堆缺页的时候再分配物理页,且不可执行
+        let vm_areas = &mut task.as_mut().vm_areas;
+        let flags = PTFlags::RW | PTFlags::US | no_execute();
+        let registered = vm_areas.insert(VmArea::new(
+            USER_TEXT_BASE,
+            USER_TEXT_BASE + user_text_size(),
+            PTFlags::US,
+            VmKind::Code,
+        )) && vm_areas.insert(VmArea::new(
+            USER_STACK_TOP - USER_STACK_SIZE,
+            USER_STACK_TOP,
+            flags,
+            VmKind::Stack,
+        )) && vm_areas.insert(VmArea::new(
+            USER_HEAP_BASE,
+            USER_HEAP_BASE,
+            flags,
+            VmKind::Heap,
+        ));
+        if !registered {
+            *vm_areas = VmAreas::new();
+            return Errno::ENOMEM;
+        }
+
+        // 映射用户代码段,代码段是只读的
+        if !map_user_text() {
+            return Errno::ENOMEM;
+        }
+
+        // 入口函数返回到user_task_return,退出任务
+        let esp = USER_STACK_TOP - size_of::<u32>() as u32;
+        let ret = (user_task_return as usize as u32).to_ne_bytes();
+        if let Err(errno) = copy_to_user(esp as usize, &ret) {
+            return errno;
+        }
 
-        let mut intr_frame = Task::get_intr_frame(task);
+        Task::enter_user_mode(target as usize as u32, esp)
+    }
+
+    /// 模拟中断返回,从entry进入用户态,用户栈的栈顶是esp,不会再返回
+    /// 中断帧放在内核栈的栈底,内核栈上原来的内容都不再需要
+    pub unsafe fn enter_user_mode(entry: u32, esp: u32) -> ! {
+        let mut intr_frame = Task::get_intr_frame(Task::current_task());
 
         let intr_frame = intr_frame.as_mut();
         // 假装发生了时钟中断 嘿嘿
@@ -434,13 +556,10 @@ impl Task {
         intr_frame.cs = USER_CODE_SELECTOR.bits() as _;
 
         intr_frame.error = KERNEL_MAGIC;
-        intr_frame.eip = target as usize as _;
+        intr_frame.eip = entry;
         intr_frame.eflags = 0b10 | 1 << 9;
-
-        let task_layout =
-            Layout::from_size_align(size_of::<Task>(), BASE_PAGE_SIZE).unwrap();
         // 用户栈地址
-        intr_frame.esp = (alloc(task_layout) as usize + BASE_PAGE_SIZE) as _;
+        intr_frame.esp = esp;
 
         // 模拟中断返回
         asm!(
@@ -453,23 +572,325 @@ impl Task {
         );
     }
 
+    /// 复制当前任务,用户空间写时复制,只能在用户态通过系统调用进入
+    /// 父任务返回子任务的pid,子任务返回0,失败返回-1
+    pub unsafe fn fork() -> usize {
+        let current = Task::current_task();
+        let intr_frame = Task::get_intr_frame(current);
+
+        // 内核态没有切换栈,中断帧不在栈底
+        if intr_frame.as_ref().cs != USER_CODE_SELECTOR.bits() as u32 {
+            return usize::MAX;
+        }
+
+        let Some(pde) =
+            fork_page_dir(current.as_ref().pde, &current.as_ref().vm_areas)
+        else {
+            return usize::MAX;
+        };
+
+        let Some(mut child) = Task::get_free_task() else {
+            destroy_page_dir(pde);
+            return usize::MAX;
+        };
+        let (kernel_stack, pid) =
+            (child.as_ref().kernel_stack, child.as_ref().pid);
+        // 拷贝PCB和中断帧,子任务使用自己的内核栈和pid
+        ptr::copy_nonoverlapping(current.as_ptr(), child.as_ptr(), 1);
+        child.as_mut().kernel_stack = kernel_stack;
+        child.as_mut().pid = pid;
+
+        let mut child_intr_frame = Task::get_intr_frame(NonNull::from(child));
+        ptr::copy_nonoverlapping(
+            intr_frame.as_ptr(),
+            child_intr_frame.as_ptr(),
+            1,
+        );
+
+        let child_mut = child.as_mut();
+        child_mut.node.next = None;
+        child_mut.node.prev = None;
+        // 新的任务从最高一级开始
+        child_mut.level = 0;
+        child_mut.ticks = child_mut.time_slice();
+        child_mut.jiffies = 0;
+        child_mut.pde = pde;
+        child_mut.killed = false;
+        child_mut.children = None;
+        child_mut.exit_code = 0;
+
+        // 子任务继承了共享内存段的挂载
+        child_mut.vm_areas.iter().for_each(|area| {
+            if let VmKind::Shm(id) = area.kind {
+                shm_fork(id);
+            }
+        });
+
+        // 子任务从系统调用返回0
+        child_intr_frame.as_mut().eax = 0;
+
+        // 任务切换之后直接从中断返回到用户态
+        let mut task_frame = Unique::new_unchecked(
+            (child_intr_frame.as_ptr() as usize - size_of::<TaskFrame>())
+                as *mut TaskFrame,
+        );
+        let task_frame_mut = task_frame.as_mut();
+        task_frame_mut.ebx = 0x11111111;
+        task_frame_mut.esi = 0x22222222;
+        task_frame_mut.edi = 0x33333333;
+        task_frame_mut.ebp = 0x44444444;
+        task_frame_mut.eip = interrupt_exit as usize as u32;
+        child_mut.stack = task_frame.as_ptr() as u32;
+
+        Task::add_child(current, NonNull::from(child));
+        Task::make_ready(NonNull::from(child));
+        child.as_ref().pid as usize
+    }
+
+    /// 当前级别的时间片,每降低一级时间片翻倍
+    pub fn time_slice(&self) -> u64 {
+        (self.priority as u64) << self.level
+    }
+
+    /// 用完了时间片的任务是计算密集的,降低一级,之后的时间片更长
+    pub unsafe fn demote(mut task: NonNull<Task>) {
+        let task_mut = task.as_mut();
+        task_mut.level = (task_mut.level + 1).min(LEVELS as u32 - 1);
+        task_mut.ticks = task_mut.time_slice();
+    }
+
+    /// 把所有就绪的任务和当前任务提升到最高一级
+    /// 阻塞和睡眠的任务不在就绪队列中,等下一次提升
+    pub unsafe fn boost() {
+        assert!(!if_enabled());
+        RUN_QUEUE.boost();
+        Task::current_task().as_mut().level = 0;
+    }
+
+    /// 内核栈的最高使用量,引导任务的栈不在内核栈区域中,返回None
+    pub fn stack_high_water(&self) -> Option<usize> {
+        kernel_stack_high_water(self.kernel_stack)
+    }
+
+    /// 任务能不能被杀死,idle和init任务不能退出
+    pub fn is_killable(task: NonNull<Task>) -> bool {
+        unsafe {
+            task != NonNull::from(IDLE_TASK) && task != NonNull::from(INIT_TASK)
+        }
+    }
+
+    /// 杀死当前任务,调度到其他任务,不会再返回
+    pub unsafe fn kill() -> ! {
+        Task::exit(KILLED_EXIT_CODE)
+    }
+
+    /// 当前任务以code退出,回收地址空间,PCB和内核栈等父任务回收,不会再返回
+    /// 子任务交给init收养
+    pub unsafe fn exit(code: i32) -> ! {
+        let mut current = Task::current_task();
+        assert_ne!(
+            current.as_ptr(),
+            IDLE_TASK.as_ptr(),
+            "idle task can not exit"
+        );
+        assert_ne!(
+            current.as_ptr(),
+            INIT_TASK.as_ptr(),
+            "init task can not exit"
+        );
+
+        enable_interrupt(false);
+        let init = NonNull::from(INIT_TASK);
+        while let Some(child) = current.as_ref().children {
+            Task::remove_child(current, child);
+            Task::add_child(init, child);
+            // 已经退出的子任务由init回收
+            if child.as_ref().state == TaskState::TaskZombie {
+                Task::wake_waiting(init);
+            }
+        }
+
+        let current_mut = current.as_mut();
+        current_mut.exit_code = code;
+        current_mut.state = match current_mut.parent {
+            Some(parent) => {
+                Task::wake_waiting(parent);
+                TaskState::TaskZombie
+            }
+            None => TaskState::TaskDied,
+        };
+
+        Task::schedule();
+        unreachable!("exited task {} scheduled again", current.as_ref().name);
+    }
+
+    /// 等待编号为pid的子任务退出并回收它,pid为None时等待任意一个子任务
+    /// 返回子任务的编号和退出码,没有这样的子任务返回ECHILD
+    /// nohang为true时子任务都没有退出就返回None,不等待
+    pub unsafe fn waitpid(
+        pid: Option<u32>,
+        nohang: bool,
+    ) -> Result<Option<(u32, i32)>, Errno> {
+        without_interrupt(|| loop {
+            let mut current = Task::current_task();
+            let is_zombie = |child: &NonNull<Task>| {
+                child.as_ref().state == TaskState::TaskZombie
+            };
+            let exited = match pid {
+                // 指定了pid的时候直接在任务表中查找
+                Some(pid) => {
+                    let child = Task::find(pid)
+                        .filter(|child| child.as_ref().parent == Some(current))
+                        .ok_or(Errno::ECHILD)?;
+                    Some(child).filter(is_zombie)
+                }
+                None => {
+                    let mut children = Task::children(current).peekable();
+                    if children.peek().is_none() {
+                        return Err(Errno::ECHILD);
+                    }
+                    children.find(is_zombie)
+                }
+            };
+
+            if let Some(child) = exited {
+                let exited = (child.as_ref().pid, child.as_ref().exit_code);
+                Task::remove_child(current, child);
+                Task::reap(Unique::from(child));
+                return Ok(Some(exited));
+            }
+
+            if nohang {
+                return Ok(None);
+            }
+
+            // 子任务退出的时候唤醒
+            current.as_mut().state = TaskState::TaskWaiting;
+            Task::schedule();
+        })
+    }
+
+    /// 父任务的编号,没有父任务返回0
+    pub fn ppid(&self) -> u32 {
+        self.parent
+            .map_or(0, |parent| unsafe { parent.as_ref().pid })
+    }
+
     pub unsafe fn task_activate(task: Unique<Task>) {
         assert_eq!(task.as_ref().magic_number, KERNEL_MAGIC);
 
         if task.as_ref().uid != KERNEL_USER {
-            TSS.esp0 = (task.as_ptr() as usize + BASE_PAGE_SIZE) as _;
+            TSS.esp0 = task.as_ref().kernel_stack;
         }
+
+        // 切换到任务的地址空间
+        switch_page_dir(task.as_ref().pde);
     }
 }
 
 /// private func
 impl Task {
+    /// 改为就绪状态,加入所在级别的就绪队列,idle不在队列中
+    unsafe fn make_ready(mut task: NonNull<Task>) {
+        task.as_mut().state = TaskState::TaskReady;
+        if task.as_ref().pid != IDLE_PID {
+            without_interrupt(|| RUN_QUEUE.push(task));
+        }
+    }
+
+    /// 任务的所有子任务
+    unsafe fn children(
+        task: NonNull<Task>,
+    ) -> impl Iterator<Item = NonNull<Task>> {
+        iter::successors(task.as_ref().children, |child| child.as_ref().sibling)
+    }
+
+    /// 把child加入parent的子任务链表
+    unsafe fn add_child(mut parent: NonNull<Task>, mut child: NonNull<Task>) {
+        child.as_mut().parent = Some(parent);
+        child.as_mut().sibling = parent.as_ref().children;
+        parent.as_mut().children = Some(child);
+    }
+
+    /// 把child移出parent的子任务链表
+    unsafe fn remove_child(
+        mut parent: NonNull<Task>,
+        mut child: NonNull<Task>,
+    ) {
+        let next = child.as_ref().sibling;
+        if parent.as_ref().children == Some(child) {
+            parent.as_mut().children = next;
+        } else if let Some(mut prev) = Task::children(parent)
+            .find(|task| task.as_ref().sibling == Some(child))
+        {
+            prev.as_mut().sibling = next;
+        }
+
+        child.as_mut().parent = None;
+        child.as_mut().sibling = None;
+    }
+
+    /// 唤醒等待子任务退出的任务
+    unsafe fn wake_waiting(task: NonNull<Task>) {
+        if task.as_ref().state == TaskState::TaskWaiting {
+            Task::make_ready(task);
+        }
+    }
+
+    /// 回收已经退出的任务的PCB和内核栈,地址空间在退出的时候已经回收
+    unsafe fn reap(task: Unique<Task>) {
+        assert_ne!(task.as_ptr(), Task::current_task().as_ptr());
+
+        without_interrupt(|| TASKS.lock().remove(task.as_ref().pid));
+        free_kernel_stack(task.as_ref().kernel_stack);
+        SlabAllocator
+            .deallocate(task.as_non_null_ptr().cast(), Layout::new::<Task>());
+    }
+
+    /// 回收所有没有父任务的已经退出的任务
+    fn reap_died() {
+        without_interrupt(|| unsafe {
+            let current = Task::current_task();
+            loop {
+                // 回收的时候要再获取任务表的锁,不能写在一行
+                let died = TASKS.lock().iter().find(|task| {
+                    task.as_ref().state == TaskState::TaskDied
+                        && task.as_ptr() != current.as_ptr()
+                });
+                let Some(task) = died else {
+                    break;
+                };
+                Task::reap(task);
+            }
+        });
+    }
+
+    /// 回收任务的地址空间,之后任务只能使用内核的页目录
+    unsafe fn release_page_dir(mut task: NonNull<Task>) {
+        let pde = task.as_ref().pde;
+        if pde == KERNEL_PAGE_DIR {
+            return;
+        }
+
+        // 卸载共享内存段,映射的页随页目录一起释放
+        task.as_ref().vm_areas.iter().for_each(|area| {
+            if let VmKind::Shm(id) = area.kind {
+                shm_detach(id);
+            }
+        });
+        task.as_mut().vm_areas = VmAreas::new();
+
+        // 内核的映射在所有的页目录中都一样,可以直接切换到内核页目录
+        switch_page_dir(KERNEL_PAGE_DIR);
+        task.as_mut().pde = KERNEL_PAGE_DIR;
+        destroy_page_dir(pde);
+    }
+
     fn get_task_frame(task: Unique<Task>) -> Unique<TaskFrame> {
         // 计算上下文的地址
-        // 栈是从高地址向低地址增长的,任务是从一页的起始位置开始分配的
-        // 把一页的末尾(高地址)用来保存任务上下文,那么上下文的起始地址就是内核栈的栈底
-        let stack =
-            task.as_ptr() as usize + BASE_PAGE_SIZE - size_of::<TaskFrame>();
+        // 栈是从高地址向低地址增长的,内核栈的栈顶用来保存任务上下文
+        let stack = unsafe { task.as_ref().kernel_stack } as usize
+            - size_of::<TaskFrame>();
         let frame = stack as *mut TaskFrame;
         unsafe { Unique::new_unchecked(frame) }
     }
@@ -487,31 +908,47 @@ impl Task {
 
     fn get_intr_frame(task: NonNull<Task>) -> NonNull<IntrFrame> {
         // 计算上下文的地址
-        // 栈是从高地址向低地址增长的,任务是从一页的起始位置开始分配的
-        // 把一页的末尾(高地址)用来保存任务上下文,那么上下文的起始地址就是内核栈的栈底
-        let stack =
-            task.as_ptr() as usize + BASE_PAGE_SIZE - size_of::<IntrFrame>();
+        // 栈是从高地址向低地址增长的,从用户态进入内核时中断帧在内核栈的栈顶
+        let stack = unsafe { task.as_ref().kernel_stack } as usize
+            - size_of::<IntrFrame>();
         let frame = stack as *mut IntrFrame;
         unsafe { NonNull::new_unchecked(frame) }
     }
 }
 
+/// 内核任务的入口,ebx中是任务函数,任务函数返回之后以0退出
+#[naked]
+#[link_section = ".text"]
+unsafe extern "C" fn task_entry() {
+    asm!(
+        "call *%ebx",
+        "push $0",
+        "call {0}",
+        sym task_exit,
+        options(noreturn, att_syntax)
+    );
+}
+
+/// 任务函数返回之后的退出
+extern "C" fn task_exit(code: i32) -> ! {
+    unsafe { Task::exit(code) }
+}
+
 /// 任务切换
 #[naked]
 #[link_section = ".text"]
-pub unsafe extern "C" fn task_switch(next: *mut Task) {
+pub unsafe extern "C" fn task_switch(current: *mut Task, next: *mut Task) {
     asm!(
         "pushl %ebp",
         "movl %esp, %ebp",
         "pushl %ebx",
         "pushl %esi",
         "pushl %edi",
-        "movl %esp, %eax",
         // current
-        "andl $0xfffff000, %eax",
+        "movl 8(%ebp), %eax",
         "movl %esp, (%eax)",
         // next
-        "movl 8(%ebp), %eax",
+        "movl 12(%ebp), %eax",
         "movl (%eax), %esp",
         "popl %edi",
         "popl %esi",
@@ -3,7 +3,7 @@ use crate::kernel::system_call::sys_call::sys_yield;
 use crate::printk;
 use core::arch::asm;
 
-pub(crate) fn idle() -> ! {
+pub(crate) fn idle() {
     enable_interrupt(true);
 
     loop {
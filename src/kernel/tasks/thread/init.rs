@@ -1,17 +1,43 @@
+use core::ptr::{null, null_mut};
+
 use crate::kernel::system_call::print::{sys_write, StdFd};
-use crate::kernel::system_call::sys_call::sys_sleep;
+use crate::kernel::system_call::sys_call::{
+    sys_execve, sys_exit, sys_fork, sys_sleep, sys_waitpid, WaitOptions,
+};
 use crate::kernel::tasks::task::Task;
 
-pub(crate) fn init() -> ! {
+pub(crate) fn init() {
     let mut use_stack = [' '; 10];
     use_stack[9] = 'a';
-    unsafe { Task::task_to_user_mode(real_init) }
-    panic!("you will never back from user mode");
+    let errno = unsafe { Task::task_to_user_mode(real_init) };
+    panic!("init can not enter user mode: {:?}", errno);
 }
 
-fn real_init() -> ! {
+/// 输出内容的长度
+const HELLO_LEN: usize = 5;
+
+/// 用户态不能访问内核的数据,输出的内容放在用户只读数据段
+#[link_section = ".rodata.user"]
+static HELLO: [u8; HELLO_LEN] = *b"hello";
+
+/// 内嵌在内核镜像中的用户程序
+#[link_section = ".rodata.user"]
+static HELLO_PATH: [u8; 11] = *b"/bin/hello\0";
+
+/// 用户态的init放在用户代码段,只能调用内联的系统调用
+#[link_section = ".text.user"]
+fn real_init() {
+    // 子任务执行内嵌的用户程序,退出之后由init回收
+    if sys_fork() == 0 {
+        let argv = [HELLO_PATH.as_ptr(), null()];
+        sys_execve(HELLO_PATH.as_ptr(), argv.as_ptr(), null());
+        sys_exit(-1);
+    }
+
     loop {
         sys_sleep(500);
-        sys_write(StdFd::Out, "hello");
+        // 回收收养的已经退出的任务
+        while sys_waitpid(-1, null_mut(), WaitOptions::WNOHANG) as isize > 0 {}
+        sys_write(StdFd::Out, HELLO.as_ptr(), HELLO_LEN);
     }
 }
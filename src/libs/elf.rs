@@ -0,0 +1,139 @@
+//! ELF32格式的可执行文件,只支持i386的静态链接程序
+use bitflags::bitflags;
+use core::mem::size_of;
+use core::ptr;
+
+/// 文件开头的魔数
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+/// 32位的文件
+const ELF_CLASS_32: u8 = 1;
+/// 小端序
+const ELF_DATA_LSB: u8 = 1;
+/// 当前的版本
+const EV_CURRENT: u8 = 1;
+/// 可执行文件
+const ET_EXEC: u16 = 2;
+/// Intel 80386
+const EM_386: u16 = 3;
+
+/// 需要装载的段
+pub const PT_LOAD: u32 = 1;
+/// 动态链接器的路径,静态链接的程序没有这个段
+pub const PT_INTERP: u32 = 3;
+
+bitflags! {
+    /// 段的访问权限
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct SegmentFlags: u32 {
+        const EXEC = 0x1;
+        const WRITE = 0x2;
+        const READ = 0x4;
+    }
+}
+
+/// 文件头
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ElfHeader {
+    pub ident: [u8; 16],
+    pub elf_type: u16,
+    pub machine: u16,
+    pub version: u32,
+    /// 程序入口
+    pub entry: u32,
+    /// 程序头表在文件中的偏移
+    pub phoff: u32,
+    pub shoff: u32,
+    pub flags: u32,
+    pub ehsize: u16,
+    /// 每个程序头的大小
+    pub phentsize: u16,
+    /// 程序头的数量
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+/// 程序头,描述一个段
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    /// 段在文件中的偏移
+    pub offset: u32,
+    /// 段装载的虚拟地址
+    pub vaddr: u32,
+    pub paddr: u32,
+    /// 段在文件中的大小
+    pub filesz: u32,
+    /// 段在内存中的大小,多出来的部分清零
+    pub memsz: u32,
+    pub flags: u32,
+    pub align: u32,
+}
+
+impl ProgramHeader {
+    pub fn flags(&self) -> SegmentFlags {
+        SegmentFlags::from_bits_truncate(self.flags)
+    }
+}
+
+/// 解析过的ELF文件
+pub struct Elf<'a> {
+    data: &'a [u8],
+    pub header: ElfHeader,
+}
+
+impl<'a> Elf<'a> {
+    /// 解析文件头,不是i386的可执行文件或者程序头表不完整返回None
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let header: ElfHeader = read(data, 0)?;
+        let ident = &header.ident;
+        if ident[..4] != ELF_MAGIC
+            || ident[4] != ELF_CLASS_32
+            || ident[5] != ELF_DATA_LSB
+            || ident[6] != EV_CURRENT
+            || header.elf_type != ET_EXEC
+            || header.machine != EM_386
+            || header.phentsize as usize != size_of::<ProgramHeader>()
+        {
+            return None;
+        }
+
+        // 程序头表要完整地在文件中
+        let table_size = header.phnum as usize * size_of::<ProgramHeader>();
+        (header.phoff as usize)
+            .checked_add(table_size)
+            .filter(|&end| end <= data.len())?;
+
+        Some(Elf { data, header })
+    }
+
+    /// 所有的程序头
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let data = self.data;
+        let phoff = self.header.phoff as usize;
+        (0..self.header.phnum as usize).map(move |index| {
+            read(data, phoff + index * size_of::<ProgramHeader>()).unwrap()
+        })
+    }
+
+    /// 段在文件中的内容,超出文件或者文件中的大小超过内存中的大小返回None
+    pub fn segment_data(&self, header: &ProgramHeader) -> Option<&'a [u8]> {
+        if header.filesz > header.memsz {
+            return None;
+        }
+
+        let start = header.offset as usize;
+        let end = start.checked_add(header.filesz as usize)?;
+        self.data.get(start..end)
+    }
+}
+
+/// 从offset处读取一个结构,文件中的数据不一定是对齐的
+fn read<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    let bytes = data.get(offset..end)?;
+    Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
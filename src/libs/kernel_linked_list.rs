@@ -194,11 +194,20 @@ impl<T> LinkedList<T> {
     #[inline]
     #[must_use]
     pub const fn new() -> Self {
+        LinkedList::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator> LinkedList<T, A> {
+    /// 使用指定的分配器分配节点,比如slab分配器
+    #[inline]
+    #[must_use]
+    pub const fn new_in(alloc: A) -> Self {
         LinkedList {
             head: None,
             tail: None,
             len: 0,
-            alloc: Global,
+            alloc,
             marker: PhantomData,
         }
     }
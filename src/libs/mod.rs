@@ -1,2 +1,3 @@
 pub mod circular_queue;
+pub mod elf;
 pub mod kernel_linked_list;
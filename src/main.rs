@@ -14,16 +14,44 @@ mod kernel;
 mod libs;
 mod mm;
 
+use crate::drivers::ide::init_ide;
 use crate::kernel::interrupts::{enable_interrupt, init_interrupt};
 use crate::kernel::system_call::init_system_call;
 use crate::kernel::tasks::init_task;
+use crate::mm::page::{
+    BOOT_PAGE_DIR, KERNEL_BASE, KERNEL_LOAD_ADDRESS, KERNEL_MEMORY_SIZE,
+    KERNEL_PAGE_TABLE,
+};
+use crate::mm::swap::init_swap;
 use core::arch::global_asm;
 use core::panic::PanicInfo;
 use x86::halt;
 
 pub const KERNEL_MAGIC: u32 = 0x20230604;
 
-global_asm!(include_str!("entry.asm"));
+#[cfg(not(feature = "pae"))]
+global_asm!(
+    include_str!("entry.asm"),
+    load_address = const KERNEL_LOAD_ADDRESS,
+    kernel_base = const KERNEL_BASE,
+    kernel_page_table = const KERNEL_PAGE_TABLE[0],
+    boot_page_dir = const BOOT_PAGE_DIR,
+    boot_pages = const KERNEL_MEMORY_SIZE >> 12,
+    kernel_pde_offset = const (KERNEL_BASE >> 22) * 4,
+);
+
+#[cfg(feature = "pae")]
+global_asm!(
+    include_str!("entry_pae.asm"),
+    load_address = const KERNEL_LOAD_ADDRESS,
+    kernel_base = const KERNEL_BASE,
+    kernel_page_table = const KERNEL_PAGE_TABLE[0],
+    boot_page_table_dir = const mm::page::BOOT_PAE_PAGE_DIR,
+    boot_page_dir = const BOOT_PAGE_DIR,
+    boot_pages = const KERNEL_MEMORY_SIZE >> 12,
+    boot_tables = const KERNEL_PAGE_TABLE.len(),
+    kernel_pdpte_offset = const (KERNEL_BASE >> 30) * 8,
+);
 
 /// 内核入口
 /// gdt放在内存映射之前初始化,避免内存被页目录占用
@@ -35,6 +63,9 @@ pub extern "C" fn rust_main() -> ! {
     init_task();
     // 初始化系统调用
     init_system_call();
+    // 初始化硬盘和交换区
+    init_ide();
+    init_swap();
     // 先打印,后开启外中断
     // 否则引导任务可能被扔进等待队列
     printlnk!("hello world, this is rust kernel");
@@ -1,10 +1,182 @@
-use linked_list_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "debug_heap")]
+use crate::mm::debug_heap;
+use crate::mm::detected::HEAP_MEMORY_SIZE;
+use crate::mm::kernel_stack::KERNEL_STACK_AREA_BASE;
+use crate::mm::page::{
+    create_kernel_page_tables, free_range, map_zeroed_page, PageIndex,
+    KERNEL_BASE, KERNEL_DIRECT_MAP_SIZE,
+};
+use crate::mm::paging::{no_execute, PTFlags, BASE_PAGE_SIZE};
+use linked_list_allocator::{Heap, LockedHeap};
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: KernelHeap = KernelHeap::empty();
+
+/// 内核堆的起始位置,在直接映射的物理内存之上
+pub const KERNEL_HEAP_BASE: u32 = KERNEL_BASE + KERNEL_DIRECT_MAP_SIZE;
+/// 内核堆虚拟地址的上限,再往上是内核栈
+const KERNEL_HEAP_END: u32 = KERNEL_STACK_AREA_BASE;
+/// 内核堆的初始大小
+pub const KERNEL_HEAP_INIT_SIZE: usize = 0x100000;
+/// 内核堆每次至少增长的大小
+const KERNEL_HEAP_GROW_SIZE: usize = 0x10000;
+/// 内核堆最多占用最大内存区域的1/4
+const KERNEL_HEAP_RATIO: u64 = 4;
+
+/// 可以增长的内核堆,空间不够的时候映射新的物理页
+pub struct KernelHeap {
+    heap: LockedHeap,
+    /// 内核堆大小的上限
+    ceiling: AtomicUsize,
+}
+
+impl KernelHeap {
+    const fn empty() -> Self {
+        KernelHeap {
+            heap: LockedHeap::empty(),
+            ceiling: AtomicUsize::new(0),
+        }
+    }
+
+    /// 在堆顶映射新的物理页,超过上限或者没有物理页返回false
+    fn grow(&self, heap: &mut Heap, layout: Layout) -> bool {
+        // 分配的时候可能需要对齐,多留出一个对齐的大小
+        let size = (layout.size() + layout.align() + BASE_PAGE_SIZE - 1)
+            .idx_mask()
+            .max(KERNEL_HEAP_GROW_SIZE);
+        if heap.size() + size > self.ceiling.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let top = heap.top() as u32;
+        if !map_heap(top, size) {
+            return false;
+        }
+
+        unsafe {
+            heap.extend(size);
+        }
+        true
+    }
+
+    /// 分配内存,空间不够的时候增长堆
+    fn allocate(&self, heap: &mut Heap, layout: Layout) -> *mut u8 {
+        loop {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
 
-pub fn init_heap(base: u64, size: usize) {
+            if !self.grow(heap, layout) {
+                return null_mut();
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "debug_heap"))]
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(&mut self.heap.lock(), layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+/// 调试模式下每次分配都加上红区,并登记没有释放的分配
+#[cfg(feature = "debug_heap")]
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(outer) = debug_heap::outer_layout(layout) else {
+            return null_mut();
+        };
+
+        let mut heap = self.heap.lock();
+        let block = self.allocate(&mut heap, outer);
+        if block.is_null() {
+            return block;
+        }
+        debug_heap::track(block, layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let outer = debug_heap::outer_layout(layout).unwrap();
+        let mut heap = self.heap.lock();
+        let block = debug_heap::untrack(ptr, layout);
+        heap.deallocate(NonNull::new_unchecked(block), outer);
+    }
+}
+
+/// 映射[vaddr, vaddr + size)给内核堆使用,失败的时候撤销已经建立的映射
+fn map_heap(vaddr: u32, size: usize) -> bool {
+    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+        if !map_zeroed_page(vaddr + offset as u32, PTFlags::RW | no_execute()) {
+            free_range(vaddr, offset);
+            return false;
+        }
+    }
+
+    true
+}
+
+/// 初始化内核堆,上限由最大的内存区域决定
+/// 堆的页表提前创建好,之后创建的页目录都能看到增长的堆
+pub fn init_heap() {
+    let ceiling = (unsafe { HEAP_MEMORY_SIZE } / KERNEL_HEAP_RATIO)
+        .min((KERNEL_HEAP_END - KERNEL_HEAP_BASE) as u64)
+        .max(KERNEL_HEAP_INIT_SIZE as u64) as usize;
+    let ceiling = ceiling.idx_mask();
+
+    assert!(
+        create_kernel_page_tables(KERNEL_HEAP_BASE, ceiling),
+        "no memory for kernel heap page table"
+    );
+    assert!(
+        map_heap(KERNEL_HEAP_BASE, KERNEL_HEAP_INIT_SIZE),
+        "no memory for kernel heap"
+    );
+
+    ALLOCATOR.ceiling.store(ceiling, Ordering::Relaxed);
     unsafe {
-        ALLOCATOR.lock().init(base as *mut u8, size);
+        ALLOCATOR
+            .heap
+            .lock()
+            .init(KERNEL_HEAP_BASE as *mut u8, KERNEL_HEAP_INIT_SIZE);
     }
 }
+
+/// 内核堆当前的大小
+pub fn heap_size() -> usize {
+    ALLOCATOR.heap.lock().size()
+}
+
+/// 内核堆已经分配出去的大小
+pub fn heap_used() -> usize {
+    ALLOCATOR.heap.lock().used()
+}
+
+/// 内核堆大小的上限
+pub fn heap_ceiling() -> usize {
+    ALLOCATOR.ceiling.load(Ordering::Relaxed)
+}
+
+/// 检查所有没有释放的分配的红区,红区被改写时panic
+#[cfg(feature = "debug_heap")]
+pub fn check_heap() {
+    let _heap = ALLOCATOR.heap.lock();
+    debug_heap::check_all();
+}
+
+/// 输出所有没有释放的分配和分配时的调用地址
+#[cfg(feature = "debug_heap")]
+pub fn dump_heap() {
+    let _heap = ALLOCATOR.heap.lock();
+    debug_heap::dump();
+}
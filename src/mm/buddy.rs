@@ -0,0 +1,252 @@
+use core::ptr::NonNull;
+use core::slice;
+
+use crate::mm::page::{phys_to_virt, virt_to_phys, PageIndex};
+
+/// 最大的阶,最大的块是2^10页,也就是4M
+pub const MAX_ORDER: usize = 10;
+/// 阶的数量
+pub const ORDER_NUMBER: usize = MAX_ORDER + 1;
+
+/// 页不是空闲块的第一页
+const NOT_FREE: u8 = u8::MAX;
+
+/// ISA DMA只能访问16M以下的内存
+const ISA_DMA_LIMIT: u64 = 0x1000000;
+/// 32位的DMA只能访问4G以下的内存
+const DMA32_LIMIT: u64 = 0x100000000;
+
+/// 内存区域,按照设备能访问的地址范围划分
+/// 区域的边界按最大的块对齐,伙伴不会跨越两个区域
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Zone {
+    /// 16M以下,ISA DMA
+    Dma = 0,
+    /// 4G以下,32位的DMA
+    Dma32 = 1,
+    /// 4G以上
+    Normal = 2,
+}
+
+impl Zone {
+    /// 区域的数量
+    pub const COUNT: usize = 3;
+
+    const ALL: [Zone; Zone::COUNT] = [Zone::Dma, Zone::Dma32, Zone::Normal];
+
+    /// 物理地址所在的区域
+    pub fn of(addr: u64) -> Zone {
+        if addr < ISA_DMA_LIMIT {
+            Zone::Dma
+        } else if addr < DMA32_LIMIT {
+            Zone::Dma32
+        } else {
+            Zone::Normal
+        }
+    }
+}
+
+/// 空闲块,存放在空闲块的第一页中,串成双向链表
+struct FreeBlock {
+    prev: Option<NonNull<FreeBlock>>,
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// 同一阶的空闲块链表
+#[derive(Copy, Clone)]
+struct FreeArea {
+    head: Option<NonNull<FreeBlock>>,
+    count: usize,
+}
+
+impl FreeArea {
+    const fn new() -> Self {
+        FreeArea {
+            head: None,
+            count: 0,
+        }
+    }
+}
+
+/// 区域的统计信息
+#[derive(Copy, Clone, Debug)]
+pub struct ZoneStats {
+    pub zone: Zone,
+    /// 空闲的页数
+    pub free_pages: usize,
+    /// 每一阶的空闲块数量
+    pub free_blocks: [usize; ORDER_NUMBER],
+}
+
+impl ZoneStats {
+    /// 最大的空闲块的阶,没有空闲块返回None
+    pub fn largest_order(&self) -> Option<usize> {
+        self.free_blocks.iter().rposition(|count| *count != 0)
+    }
+
+    /// 分配order阶的块时的碎片程度,百分比
+    /// 表示空闲的页中,有多少在比order小的块里,无法满足这次分配
+    pub fn fragmentation(&self, order: usize) -> usize {
+        if self.free_pages == 0 {
+            return 0;
+        }
+
+        let usable: usize = self.free_blocks[order..]
+            .iter()
+            .enumerate()
+            .map(|(offset, count)| count << (order + offset))
+            .sum();
+        (self.free_pages - usable) * 100 / self.free_pages
+    }
+}
+
+/// 伙伴分配器,管理空闲的物理页,每个区域有自己的空闲链表
+pub struct BuddyAllocator {
+    /// 每一页的状态,空闲块的第一页记录块的阶,其他的页为NOT_FREE
+    orders: *mut u8,
+    /// 管理的物理页数量,从物理地址0开始
+    total: usize,
+    zones: [[FreeArea; ORDER_NUMBER]; Zone::COUNT],
+}
+
+unsafe impl Send for BuddyAllocator {}
+
+impl BuddyAllocator {
+    pub const fn empty() -> Self {
+        BuddyAllocator {
+            orders: core::ptr::null_mut(),
+            total: 0,
+            zones: [[FreeArea::new(); ORDER_NUMBER]; Zone::COUNT],
+        }
+    }
+
+    /// 状态数组放在orders的位置,开始时没有空闲的页
+    pub unsafe fn init(&mut self, orders: *mut u8, total: usize) {
+        self.orders = orders;
+        self.total = total;
+        self.orders().fill(NOT_FREE);
+    }
+
+    fn orders(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.orders, self.total) }
+    }
+
+    /// 把块插入空闲链表的头部
+    fn push(&mut self, index: usize, order: usize) {
+        let zone = Zone::of(index.page() as u64) as usize;
+        let area = &mut self.zones[zone][order];
+        let block = phys_to_virt(index.page() as u32) as *mut FreeBlock;
+
+        unsafe {
+            block.write(FreeBlock {
+                prev: None,
+                next: area.head,
+            });
+            if let Some(mut head) = area.head {
+                head.as_mut().prev = NonNull::new(block);
+            }
+        }
+        area.head = NonNull::new(block);
+        area.count += 1;
+        self.orders()[index] = order as u8;
+    }
+
+    /// 把块从空闲链表中移除
+    fn remove(&mut self, index: usize, order: usize) {
+        let zone = Zone::of(index.page() as u64) as usize;
+        let area = &mut self.zones[zone][order];
+        let block = phys_to_virt(index.page() as u32) as *mut FreeBlock;
+
+        unsafe {
+            let FreeBlock { prev, next } = block.read();
+            match prev {
+                Some(mut prev) => prev.as_mut().next = next,
+                None => area.head = next,
+            }
+            if let Some(mut next) = next {
+                next.as_mut().prev = prev;
+            }
+        }
+        area.count -= 1;
+        self.orders()[index] = NOT_FREE;
+    }
+
+    /// 从区域中分配一个order阶的块,返回第一页的索引
+    fn alloc_in(&mut self, zone: Zone, order: usize) -> Option<usize> {
+        let found = (order..ORDER_NUMBER)
+            .find(|order| self.zones[zone as usize][*order].head.is_some())?;
+
+        let head = self.zones[zone as usize][found].head?;
+        let index = virt_to_phys(head.as_ptr() as usize).idx() as usize;
+        self.remove(index, found);
+
+        // 大块拆分,高地址的一半放回空闲链表
+        for order in (order..found).rev() {
+            self.push(index + (1 << order), order);
+        }
+        Some(index)
+    }
+
+    /// 分配一个order阶的块,地址在zone及以下的区域中
+    /// 优先从高的区域分配,把低端内存留给DMA
+    pub fn alloc(&mut self, order: usize, zone: Zone) -> Option<u32> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        Zone::ALL[..=zone as usize]
+            .iter()
+            .rev()
+            .find_map(|zone| self.alloc_in(*zone, order))
+            .map(|index| index.page() as u32)
+    }
+
+    /// 释放一个order阶的块,和空闲的伙伴合并
+    pub fn free(&mut self, addr: u32, order: usize) {
+        let mut index = addr.idx() as usize;
+        let mut order = order;
+        assert_eq!(
+            index & ((1 << order) - 1),
+            0,
+            "free unaligned block {:#x}",
+            addr
+        );
+        assert!(
+            index + (1 << order) <= self.total,
+            "free block {:#x} out of range",
+            addr
+        );
+
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if buddy + (1 << order) > self.total
+                || self.orders()[buddy] != order as u8
+            {
+                break;
+            }
+
+            self.remove(buddy, order);
+            index = index.min(buddy);
+            order += 1;
+        }
+
+        self.push(index, order);
+    }
+
+    /// 所有区域的统计信息
+    pub fn stats(&self) -> [ZoneStats; Zone::COUNT] {
+        Zone::ALL.map(|zone| {
+            let free_blocks = self.zones[zone as usize].map(|area| area.count);
+            let free_pages = free_blocks
+                .iter()
+                .enumerate()
+                .map(|(order, count)| count << order)
+                .sum();
+            ZoneStats {
+                zone,
+                free_pages,
+                free_blocks,
+            }
+        })
+    }
+}
@@ -0,0 +1,248 @@
+//! 调试用的内核堆,开启debug_heap特性后包装内核堆的分配和释放
+//! 每次分配的数据前后加上红区,释放时检查红区,并用毒值填充释放的内存
+//! 没有释放的分配串成链表,记录分配时的调用地址,用来定位泄漏
+//! 调用地址通过ebp回溯,需要目标配置中开启帧指针
+//! 这里的函数都要在持有内核堆的锁的时候调用
+use core::alloc::Layout;
+use core::arch::asm;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+use crate::mm::kernel_stack::KERNEL_STACK_SIZE;
+use crate::printlnk;
+
+/// 红区的大小
+const REDZONE_SIZE: usize = 16;
+/// 红区填充的字节
+const REDZONE_BYTE: u8 = 0xFD;
+/// 新分配的内存填充的字节,用来发现使用未初始化的内存
+const ALLOC_POISON: u8 = 0xCD;
+/// 释放的内存填充的字节,用来发现释放之后的使用
+const FREE_POISON: u8 = 0xDD;
+/// 分配头的魔数
+const HEADER_MAGIC: u32 = 0x48454150;
+/// 释放之后分配头的魔数,用来发现重复释放
+const FREED_MAGIC: u32 = 0x46524545;
+/// 记录的调用地址的层数
+pub const TRACE_DEPTH: usize = 4;
+/// 释放之后堆在内存块的开头写入空闲块的大小和链表指针
+/// 分配头放在这之后,释放之后还能保留FREED_MAGIC
+const HOLE_SIZE: usize = size_of::<usize>() * 2;
+
+/// 分配头,紧挨着前面的红区,记录分配的信息
+#[repr(C)]
+struct AllocHeader {
+    magic: u32,
+    /// 用户请求的大小
+    size: usize,
+    /// 用户请求的对齐
+    align: usize,
+    prev: Option<NonNull<AllocHeader>>,
+    next: Option<NonNull<AllocHeader>>,
+    /// 分配时的调用地址,由近到远
+    callers: [usize; TRACE_DEPTH],
+}
+
+/// 没有释放的分配
+struct Allocations {
+    head: Option<NonNull<AllocHeader>>,
+    count: usize,
+    bytes: usize,
+}
+
+/// 没有释放的分配,由内核堆的锁保护
+static mut ALLOCATIONS: Allocations = Allocations {
+    head: None,
+    count: 0,
+    bytes: 0,
+};
+
+/// 用户数据相对于实际分配的起始位置的偏移
+/// 前面依次是空闲块的位置、分配头和红区,偏移按用户的对齐要求对齐
+fn data_offset(layout: Layout) -> usize {
+    let align = layout.align().max(align_of::<AllocHeader>());
+    let offset = HOLE_SIZE + size_of::<AllocHeader>() + REDZONE_SIZE;
+    (offset + align - 1) & !(align - 1)
+}
+
+/// 加上分配头和红区之后实际分配的布局
+pub fn outer_layout(layout: Layout) -> Option<Layout> {
+    let align = layout.align().max(align_of::<AllocHeader>());
+    let size = data_offset(layout)
+        .checked_add(layout.size())?
+        .checked_add(REDZONE_SIZE)?;
+    Layout::from_size_align(size, align).ok()
+}
+
+/// 用户数据前面的分配头
+unsafe fn header_of(data: *mut u8) -> *mut AllocHeader {
+    data.sub(REDZONE_SIZE + size_of::<AllocHeader>()) as *mut AllocHeader
+}
+
+/// 用户数据前后的红区
+unsafe fn redzones(data: *mut u8, size: usize) -> [&'static mut [u8]; 2] {
+    [
+        slice::from_raw_parts_mut(data.sub(REDZONE_SIZE), REDZONE_SIZE),
+        slice::from_raw_parts_mut(data.add(size), REDZONE_SIZE),
+    ]
+}
+
+/// 通过ebp回溯调用地址,跳过分配器自身的几层
+/// 下一帧必须在当前帧之上并且在同一个内核栈中,否则停止
+fn backtrace(callers: &mut [usize; TRACE_DEPTH]) {
+    // 返回到分配器自身的层数: backtrace, track, KernelHeap::alloc
+    // 剩下的第一层是__rust_alloc的调用者
+    const SKIP: usize = 3;
+
+    let mut ebp: usize;
+    unsafe { asm!("mov {}, ebp", out(reg) ebp) };
+
+    let mut depth = 0;
+    while ebp != 0
+        && ebp & (size_of::<usize>() - 1) == 0
+        && depth < SKIP + TRACE_DEPTH
+    {
+        let (next, ret) = unsafe {
+            (*(ebp as *const usize), *((ebp as *const usize).add(1)))
+        };
+        if depth >= SKIP {
+            callers[depth - SKIP] = ret;
+        }
+        depth += 1;
+
+        if next <= ebp || next - ebp > KERNEL_STACK_SIZE {
+            break;
+        }
+        ebp = next;
+    }
+}
+
+/// 在实际分配的内存中建立分配头和红区,登记到链表,返回用户数据的地址
+pub unsafe fn track(block: *mut u8, layout: Layout) -> *mut u8 {
+    let data = block.add(data_offset(layout));
+    let header = header_of(data);
+
+    let mut callers = [0; TRACE_DEPTH];
+    backtrace(&mut callers);
+
+    let allocations = &mut *ptr::addr_of_mut!(ALLOCATIONS);
+    header.write(AllocHeader {
+        magic: HEADER_MAGIC,
+        size: layout.size(),
+        align: layout.align(),
+        prev: None,
+        next: allocations.head,
+        callers,
+    });
+    if let Some(mut head) = allocations.head {
+        head.as_mut().prev = NonNull::new(header);
+    }
+    allocations.head = NonNull::new(header);
+    allocations.count += 1;
+    allocations.bytes += layout.size();
+
+    redzones(data, layout.size())
+        .into_iter()
+        .for_each(|zone| zone.fill(REDZONE_BYTE));
+    ptr::write_bytes(data, ALLOC_POISON, layout.size());
+    data
+}
+
+/// 检查一次分配的分配头和红区,损坏的时候panic
+unsafe fn check(header: *const AllocHeader, data: *mut u8) {
+    let header = &*header;
+    assert_ne!(
+        header.magic, FREED_MAGIC,
+        "[DEBUG HEAP] double free {:p}",
+        data
+    );
+    assert_eq!(
+        header.magic, HEADER_MAGIC,
+        "[DEBUG HEAP] corrupted header {:p}",
+        data
+    );
+
+    let [front, back] = redzones(data, header.size);
+    for (name, zone) in [("underflow", front), ("overflow", back)] {
+        if let Some(offset) = zone.iter().position(|byte| *byte != REDZONE_BYTE)
+        {
+            panic!(
+                "[DEBUG HEAP] {} {:p} size {} at redzone offset {}, allocated by {:x?}",
+                name, data, header.size, offset, header.callers
+            );
+        }
+    }
+}
+
+/// 检查用户数据的分配头和红区,从链表中移除并填充毒值
+/// 返回实际分配的起始位置
+pub unsafe fn untrack(data: *mut u8, layout: Layout) -> *mut u8 {
+    let header = header_of(data);
+    check(header, data);
+    assert!(
+        (*header).size == layout.size() && (*header).align == layout.align(),
+        "[DEBUG HEAP] free {:p} with size {} align {}, allocated with size {} align {}",
+        data,
+        layout.size(),
+        layout.align(),
+        (*header).size,
+        (*header).align
+    );
+
+    let allocations = &mut *ptr::addr_of_mut!(ALLOCATIONS);
+    let AllocHeader { prev, next, .. } = header.read();
+    match prev {
+        Some(mut prev) => prev.as_mut().next = next,
+        None => allocations.head = next,
+    }
+    if let Some(mut next) = next {
+        next.as_mut().prev = prev;
+    }
+    allocations.count -= 1;
+    allocations.bytes -= layout.size();
+
+    (*header).magic = FREED_MAGIC;
+    ptr::write_bytes(data, FREE_POISON, layout.size());
+    data.sub(data_offset(layout))
+}
+
+/// 检查所有没有释放的分配的红区
+pub fn check_all() {
+    let mut node = unsafe { (*ptr::addr_of!(ALLOCATIONS)).head };
+    while let Some(header) = node {
+        let header = header.as_ptr();
+        unsafe {
+            let data = (header as *mut u8)
+                .add(size_of::<AllocHeader>() + REDZONE_SIZE);
+            check(header, data);
+            node = (*header).next;
+        }
+    }
+}
+
+/// 输出所有没有释放的分配,调用地址可以在system.map中查找
+pub fn dump() {
+    let allocations = unsafe { &*ptr::addr_of!(ALLOCATIONS) };
+    printlnk!(
+        "[DEBUG HEAP] {} allocations, {} bytes outstanding",
+        allocations.count,
+        allocations.bytes
+    );
+
+    let mut node = allocations.head;
+    while let Some(header) = node {
+        let header = unsafe { header.as_ref() };
+        let data = header as *const AllocHeader as usize
+            + size_of::<AllocHeader>()
+            + REDZONE_SIZE;
+        printlnk!(
+            "  {:#x} size {} align {} by {:x?}",
+            data,
+            header.size,
+            header.align,
+            header.callers
+        );
+        node = header.next;
+    }
+}
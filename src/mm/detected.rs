@@ -1,14 +1,26 @@
 use crate::KERNEL_MAGIC;
 use core::slice;
 
+use crate::mm::frame::init_frame_allocator;
 use crate::mm::page::KERNEL_MEMORY_SIZE;
 
 const MEMORY_BASE: u64 = 0x100000;
 const ALIGN_MASK: u64 = 0xfff;
+/// 最多记录的ARDS数量
+const ARDS_MAX: usize = 32;
 
 pub static mut HEAP_MEMORY_BASE: u64 = 0;
 pub static mut HEAP_MEMORY_SIZE: u64 = 0;
 
+/// loader探测到的ARDS,loader所在的内存之后会被页目录覆盖,所以需要拷贝一份
+pub static mut ARDS_TABLE: [Ards; ARDS_MAX] = [Ards {
+    base: 0,
+    size: 0,
+    state: 0,
+}; ARDS_MAX];
+/// ARDS的数量
+pub static mut ARDS_COUNT: usize = 0;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Ards {
@@ -39,6 +51,10 @@ pub unsafe fn memory_init(kernel_magic: u32, addrs_count: *const u32) {
         slice::from_raw_parts(addrs_array, count as usize)
     };
 
+    // 拷贝一份,供页帧分配器使用
+    ARDS_COUNT = addrs_slice.len().min(ARDS_MAX);
+    ARDS_TABLE[..ARDS_COUNT].copy_from_slice(&addrs_slice[..ARDS_COUNT]);
+
     // 在这里使用 addrs_array 数组
     for addr in addrs_slice {
         if addr.is_usable() && addr.size > HEAP_MEMORY_SIZE {
@@ -60,4 +76,7 @@ pub unsafe fn memory_init(kernel_magic: u32, addrs_count: *const u32) {
     assert_eq!(HEAP_MEMORY_BASE, MEMORY_BASE);
     // 必须是4K对齐
     assert_eq!(HEAP_MEMORY_SIZE & ALIGN_MASK, 0);
+
+    // 页帧的引用计数数组放在1M的位置
+    init_frame_allocator(&ARDS_TABLE[..ARDS_COUNT], HEAP_MEMORY_BASE as u32);
 }
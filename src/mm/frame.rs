@@ -0,0 +1,338 @@
+use core::slice;
+
+use crate::kernel::sync::mutex::Mutex;
+use crate::mm::buddy::{BuddyAllocator, Zone, ZoneStats};
+use crate::mm::detected::Ards;
+use crate::mm::page::{
+    phys_to_virt, virt_to_phys, PageIndex, KERNEL_DIRECT_MAP_SIZE,
+    KERNEL_MEMORY_SIZE, KERNEL_PAGE_DIR, KERNEL_PAGE_TABLE,
+};
+use crate::mm::paging::{PhysAddr, BASE_PAGE_SIZE, MAX_PHYS_MEMORY};
+
+/// 1M以下的低端内存,BIOS、loader、内核、页目录和页表都在这里
+const LOW_MEMORY_SIZE: u32 = 0x100000;
+
+/// 页帧不可用(保留或者不存在),永远不会被分配和释放
+const FRAME_RESERVED: u8 = u8::MAX;
+
+/// 物理页帧分配器,参考onix的memory_map
+/// 每一个物理页对应一个字节的引用计数,0表示空闲
+/// 直接映射的空闲页由伙伴分配器管理
+/// 直接映射之上的高端内存只分配给用户,在引用计数数组中查找空闲页
+pub struct FrameAllocator {
+    /// 引用计数数组的起始地址
+    map: *mut u8,
+    /// 管理的物理页数量,从物理地址0开始
+    total: usize,
+    /// 直接映射的物理页数量,这之上是高端内存
+    direct: usize,
+    /// 可以分配的物理页数量,不包括保留的页
+    usable: usize,
+    /// 高端内存中可以分配的物理页数量
+    high_usable: usize,
+    /// 直接映射的空闲物理页数量
+    free: usize,
+    /// 高端内存的空闲物理页数量
+    high_free: usize,
+    /// 下一次查找高端内存空闲页的位置
+    high_hint: usize,
+    buddy: BuddyAllocator,
+}
+
+unsafe impl Send for FrameAllocator {}
+
+/// 物理页帧分配器
+static FRAME_ALLOCATOR: Mutex<FrameAllocator> =
+    Mutex::new(FrameAllocator::empty());
+
+extern "C" {
+    /// 链接脚本中内核的起始位置
+    static skernel: u8;
+    /// 链接脚本中内核的结束位置
+    static ekernel: u8;
+}
+
+impl FrameAllocator {
+    const fn empty() -> Self {
+        FrameAllocator {
+            map: core::ptr::null_mut(),
+            total: 0,
+            direct: 0,
+            usable: 0,
+            high_usable: 0,
+            free: 0,
+            high_free: 0,
+            high_hint: 0,
+            buddy: BuddyAllocator::empty(),
+        }
+    }
+
+    fn map(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.map, self.total) }
+    }
+
+    /// 将[start, end)范围内的页标记为保留,只能在页交给伙伴分配器之前调用
+    fn reserve(&mut self, start: u32, end: u32) {
+        let end = (end as usize + BASE_PAGE_SIZE - 1).idx().min(self.total);
+        let start = (start.idx() as usize).min(end);
+        self.map()[start..end].fill(FRAME_RESERVED);
+    }
+
+    /// 把[start, end)范围内空闲的页交给伙伴分配器
+    fn add_free(&mut self, start: usize, end: usize) {
+        for index in start..end.min(self.direct) {
+            if self.map()[index] == 0 {
+                self.buddy.free(index.page() as u32, 0);
+                self.free += 1;
+            }
+        }
+    }
+
+    /// 分配2^order个连续的物理页,地址在zone及以下的区域中
+    fn alloc(&mut self, order: usize, zone: Zone) -> Option<u32> {
+        let addr = self.buddy.alloc(order, zone)?;
+        let index = addr.idx() as usize;
+        self.map()[index..index + (1 << order)].fill(1);
+        self.free -= 1 << order;
+        Some(addr)
+    }
+
+    /// 从高端内存中分配一个物理页,从上次分配的位置开始循环查找
+    fn alloc_high(&mut self) -> Option<PhysAddr> {
+        if self.high_free == 0 {
+            return None;
+        }
+
+        let (direct, hint) = (self.direct, self.high_hint);
+        let index = (hint..self.total)
+            .chain(direct..hint)
+            .find(|index| self.map()[*index] == 0)?;
+        self.map()[index] = 1;
+        self.high_free -= 1;
+        self.high_hint = index + 1;
+        Some((index as PhysAddr).page())
+    }
+
+    /// 引用计数减一,减到0就释放
+    fn free(&mut self, addr: PhysAddr) {
+        let index = addr.idx() as usize;
+        assert!(index < self.total, "free frame {:#x} out of range", addr);
+
+        let count = &mut self.map()[index];
+        assert_ne!(*count, 0, "free frame {:#x} twice", addr);
+        assert_ne!(*count, FRAME_RESERVED, "free reserved frame {:#x}", addr);
+
+        *count -= 1;
+        if *count != 0 {
+            return;
+        }
+
+        if index < self.direct {
+            self.buddy.free(addr as u32, 0);
+            self.free += 1;
+        } else {
+            self.high_free += 1;
+        }
+    }
+
+    /// 引用计数加一
+    fn share(&mut self, addr: PhysAddr) {
+        let index = addr.idx() as usize;
+        assert!(index < self.total, "share frame {:#x} out of range", addr);
+
+        let count = &mut self.map()[index];
+        assert_ne!(*count, 0, "share free frame {:#x}", addr);
+        assert_ne!(*count, FRAME_RESERVED, "share reserved frame {:#x}", addr);
+        assert!(
+            *count < FRAME_RESERVED - 1,
+            "share frame {:#x} too many",
+            addr
+        );
+
+        *count += 1;
+    }
+}
+
+/// 用ARDS中所有可用的区域初始化物理页帧分配器
+/// 引用计数数组放在物理地址`map_base`的位置,必须在启动页表映射的范围内
+pub unsafe fn init_frame_allocator(regions: &[Ards], map_base: u32) {
+    // 分页模式能访问的内存都要管理,直接映射之上的是高端内存
+    let memory_top = regions
+        .iter()
+        .filter(|ards| ards.is_usable())
+        .map(|ards| (ards.base + ards.size).min(MAX_PHYS_MEMORY))
+        .max()
+        .unwrap_or(0);
+
+    let total = memory_top.idx() as usize;
+    let direct = total.min(KERNEL_DIRECT_MAP_SIZE.idx() as usize);
+    // 引用计数数组之后是伙伴分配器的状态数组,伙伴分配器只管理直接映射的页
+    let map_pages = (total + direct + BASE_PAGE_SIZE - 1) / BASE_PAGE_SIZE;
+    assert!(
+        map_base as usize + map_pages.page() <= KERNEL_MEMORY_SIZE,
+        "frame map {:#x} not mapped",
+        map_base
+    );
+
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator.map = phys_to_virt(map_base) as *mut u8;
+    allocator.total = total;
+    allocator.direct = direct;
+    allocator.free = 0;
+    allocator
+        .buddy
+        .init(phys_to_virt(map_base + total as u32) as *mut u8, direct);
+
+    // 默认所有的页都不可用
+    allocator.map().fill(FRAME_RESERVED);
+
+    // 可用区域内完整的页才能被分配
+    for ards in regions.iter().filter(|ards| ards.is_usable()) {
+        if ards.base >= memory_top {
+            continue;
+        }
+
+        let start = (ards.base + BASE_PAGE_SIZE as PhysAddr - 1).idx() as usize;
+        let end = (ards.base + ards.size).min(memory_top).idx() as usize;
+
+        allocator.map()[start..end].fill(0);
+    }
+
+    // 低端内存,包括内核、页目录和内核页表
+    allocator.reserve(0, LOW_MEMORY_SIZE);
+    allocator.reserve(KERNEL_PAGE_DIR, KERNEL_PAGE_DIR + 1);
+    KERNEL_PAGE_TABLE
+        .iter()
+        .for_each(|table| allocator.reserve(*table, *table + 1));
+    allocator.reserve(
+        virt_to_phys(&skernel as *const u8 as usize),
+        virt_to_phys(&ekernel as *const u8 as usize),
+    );
+    // 引用计数数组自身
+    allocator.reserve(map_base, map_base + map_pages.page() as u32);
+
+    // 空闲链表存放在空闲页中,现在只能访问启动时映射的8M
+    allocator.add_free(0, KERNEL_MEMORY_SIZE.idx());
+
+    // 高端内存不需要映射,直接统计空闲页
+    allocator.high_free = allocator.map()[direct..]
+        .iter()
+        .filter(|count| **count == 0)
+        .count();
+    allocator.high_usable = allocator.high_free;
+    allocator.high_hint = direct;
+    allocator.usable =
+        allocator.map().iter().filter(|count| **count == 0).count();
+}
+
+/// 内核页目录映射了全部的物理内存之后,把8M以上的空闲页交给伙伴分配器
+pub fn init_high_frames() {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let direct = allocator.direct;
+    allocator.add_free(KERNEL_MEMORY_SIZE.idx(), direct);
+}
+
+/// 分配一个物理页
+pub fn alloc_frame() -> Option<u32> {
+    FRAME_ALLOCATOR.lock().alloc(0, Zone::Normal)
+}
+
+/// 分配一个映射给用户的物理页,优先使用高端内存
+/// 内核访问这样的页要通过临时映射
+pub fn alloc_user_frame() -> Option<PhysAddr> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator
+        .alloc_high()
+        .or_else(|| allocator.alloc(0, Zone::Normal).map(PhysAddr::from))
+}
+
+/// 分配2^order个物理上连续的页,按块的大小对齐,地址在zone及以下的区域中
+/// 给需要DMA的驱动使用
+pub fn alloc_pages(order: usize, zone: Zone) -> Option<u32> {
+    FRAME_ALLOCATOR.lock().alloc(order, zone)
+}
+
+/// 释放alloc_pages分配的2^order个物理页
+pub fn free_pages(addr: u32, order: usize) {
+    free_contiguous(addr, 1 << order)
+}
+
+/// 分配连续的count个物理页,返回第一页的物理地址
+pub fn alloc_contiguous(count: usize) -> Option<u32> {
+    if count == 0 {
+        return None;
+    }
+
+    let order = count.next_power_of_two().trailing_zeros() as usize;
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let addr = allocator.alloc(order, Zone::Normal)?;
+    // 多出来的页还给伙伴分配器
+    (count..1 << order)
+        .for_each(|index| allocator.free((addr + index.page() as u32).into()));
+    Some(addr)
+}
+
+/// 释放一个物理页
+pub fn free_frame(addr: PhysAddr) {
+    FRAME_ALLOCATOR.lock().free(addr)
+}
+
+/// 释放连续的count个物理页
+pub fn free_contiguous(addr: u32, count: usize) {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    (0..count)
+        .for_each(|index| allocator.free((addr + index.page() as u32).into()));
+}
+
+/// 物理页被多个地址空间共享,引用计数加一
+pub fn share_frame(addr: PhysAddr) {
+    FRAME_ALLOCATOR.lock().share(addr)
+}
+
+/// 物理页的引用计数
+pub fn frame_ref_count(addr: PhysAddr) -> u8 {
+    let index = addr.idx() as usize;
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    assert!(index < allocator.total, "frame {:#x} out of range", addr);
+    allocator.map()[index]
+}
+
+/// 被管理的物理内存的大小,包括高端内存
+pub fn memory_top() -> PhysAddr {
+    (FRAME_ALLOCATOR.lock().total as PhysAddr).page()
+}
+
+/// 空闲的物理页数量,包括高端内存
+pub fn free_frames() -> usize {
+    let allocator = FRAME_ALLOCATOR.lock();
+    allocator.free + allocator.high_free
+}
+
+/// 物理页的统计信息,单位是页
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameStats {
+    /// 可以分配的物理页数量,包括高端内存
+    pub total: usize,
+    /// 空闲的物理页数量,包括高端内存
+    pub free: usize,
+    /// 高端内存中可以分配的物理页数量
+    pub high_total: usize,
+    /// 高端内存中空闲的物理页数量
+    pub high_free: usize,
+}
+
+/// 物理页的统计信息
+pub fn frame_stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.lock();
+    FrameStats {
+        total: allocator.usable,
+        free: allocator.free + allocator.high_free,
+        high_total: allocator.high_usable,
+        high_free: allocator.high_free,
+    }
+}
+
+/// 每个区域的空闲块统计,用来观察碎片情况
+pub fn zone_stats() -> [ZoneStats; Zone::COUNT] {
+    FRAME_ALLOCATOR.lock().buddy.stats()
+}
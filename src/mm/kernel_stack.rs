@@ -0,0 +1,139 @@
+use core::slice;
+
+use crate::kernel::sync::mutex::Mutex;
+use crate::mm::frame::{alloc_frame, free_frame};
+use crate::mm::page::{
+    create_kernel_page_tables, free_range, map_page, phys_to_virt, PageIndex,
+    KMAP_BASE,
+};
+use crate::mm::paging::{no_execute, PTFlags, BASE_PAGE_SIZE};
+
+/// 内核栈区域的大小
+const KERNEL_STACK_AREA_SIZE: usize = 0x1000000;
+/// 内核栈区域的起始位置,在内核堆之上,临时映射区域之下
+pub const KERNEL_STACK_AREA_BASE: u32 =
+    KMAP_BASE - KERNEL_STACK_AREA_SIZE as u32;
+/// 每个内核栈的页数,可以按需调整
+pub const KERNEL_STACK_PAGES: usize = 4;
+/// 内核栈的大小
+pub const KERNEL_STACK_SIZE: usize = KERNEL_STACK_PAGES * BASE_PAGE_SIZE;
+/// 每个内核栈占用的虚拟地址,最低的一页是不映射的保护页
+const KERNEL_STACK_SLOT_SIZE: usize = KERNEL_STACK_SIZE + BASE_PAGE_SIZE;
+/// 内核栈的最大数量
+const KERNEL_STACK_SLOTS: usize =
+    KERNEL_STACK_AREA_SIZE / KERNEL_STACK_SLOT_SIZE;
+
+/// 未使用的栈填充的魔数,用来统计栈的最高使用量
+const KERNEL_STACK_POISON: u32 = 0x5A5A5A5A;
+
+/// 内核栈的占用情况
+static KERNEL_STACKS: Mutex<[bool; KERNEL_STACK_SLOTS]> =
+    Mutex::new([false; KERNEL_STACK_SLOTS]);
+
+/// 第index个内核栈的栈底,保护页之上
+fn stack_bottom(index: usize) -> u32 {
+    KERNEL_STACK_AREA_BASE
+        + (index * KERNEL_STACK_SLOT_SIZE + BASE_PAGE_SIZE) as u32
+}
+
+/// 栈顶对应的内核栈编号
+fn stack_index(top: u32) -> usize {
+    let offset = (top - KERNEL_STACK_AREA_BASE) as usize;
+    assert_eq!(
+        offset % KERNEL_STACK_SLOT_SIZE,
+        0,
+        "invalid kernel stack {:#x}",
+        top
+    );
+    offset / KERNEL_STACK_SLOT_SIZE - 1
+}
+
+/// 提前创建内核栈区域的页表,所有的地址空间共享
+/// 否则其他地址空间访问新的内核栈时缺页,而缺页需要压栈
+pub fn init_kernel_stacks() {
+    assert!(
+        create_kernel_page_tables(
+            KERNEL_STACK_AREA_BASE,
+            KERNEL_STACK_AREA_SIZE
+        ),
+        "no memory for kernel stack page table"
+    );
+}
+
+/// 分配一个内核栈,返回栈顶
+pub fn alloc_kernel_stack() -> Option<u32> {
+    let mut stacks = KERNEL_STACKS.lock();
+    let index = stacks.iter().position(|used| !used)?;
+    let bottom = stack_bottom(index);
+
+    for offset in (0..KERNEL_STACK_SIZE).step_by(BASE_PAGE_SIZE) {
+        let Some(frame) = alloc_frame() else {
+            free_range(bottom, offset);
+            return None;
+        };
+
+        // 填充魔数,之后用来统计栈的使用量
+        let page = unsafe {
+            slice::from_raw_parts_mut(
+                phys_to_virt(frame) as *mut u32,
+                BASE_PAGE_SIZE / 4,
+            )
+        };
+        page.fill(KERNEL_STACK_POISON);
+
+        let flags = PTFlags::RW | no_execute();
+        if !map_page(bottom + offset as u32, frame.into(), flags) {
+            free_frame(frame.into());
+            free_range(bottom, offset);
+            return None;
+        }
+    }
+
+    stacks[index] = true;
+    Some(bottom + KERNEL_STACK_SIZE as u32)
+}
+
+/// 释放栈顶为top的内核栈
+pub fn free_kernel_stack(top: u32) {
+    let index = stack_index(top);
+    let mut stacks = KERNEL_STACKS.lock();
+    assert!(stacks[index], "free kernel stack {:#x} twice", top);
+
+    free_range(stack_bottom(index), KERNEL_STACK_SIZE);
+    stacks[index] = false;
+}
+
+/// 已经分配的内核栈数量
+pub fn kernel_stack_count() -> usize {
+    KERNEL_STACKS.lock().iter().filter(|used| **used).count()
+}
+
+/// 地址是否在内核栈的保护页中
+pub fn is_kernel_stack_guard(vaddr: u32) -> bool {
+    let Some(offset) = vaddr.checked_sub(KERNEL_STACK_AREA_BASE) else {
+        return false;
+    };
+
+    (offset as usize) < KERNEL_STACK_AREA_SIZE
+        && (offset as usize % KERNEL_STACK_SLOT_SIZE).idx() == 0
+}
+
+/// 内核栈的最高使用量,栈不在内核栈区域中(比如引导任务的栈)返回None
+pub fn kernel_stack_high_water(top: u32) -> Option<usize> {
+    let offset = top.checked_sub(KERNEL_STACK_AREA_BASE)? as usize;
+    if offset == 0 || offset > KERNEL_STACK_AREA_SIZE {
+        return None;
+    }
+
+    let bottom = top as usize - KERNEL_STACK_SIZE;
+    let stack = unsafe {
+        slice::from_raw_parts(bottom as *const u32, KERNEL_STACK_SIZE / 4)
+    };
+
+    // 从栈底开始,第一个被改写的位置就是栈的最高位置
+    let unused = stack
+        .iter()
+        .position(|word| *word != KERNEL_STACK_POISON)
+        .unwrap_or(stack.len());
+    Some(KERNEL_STACK_SIZE - unused * 4)
+}
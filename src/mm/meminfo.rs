@@ -0,0 +1,114 @@
+use core::fmt;
+
+use crate::mm::allocator::{heap_ceiling, heap_size, heap_used};
+use crate::mm::frame::frame_stats;
+use crate::mm::kernel_stack::{kernel_stack_count, KERNEL_STACK_PAGES};
+use crate::mm::page::page_table_pages;
+use crate::mm::paging::BASE_PAGE_SIZE;
+use crate::mm::shm::shm_pages;
+use crate::mm::slab::slab_stats;
+use crate::mm::swap::swap_stats;
+
+/// 输出时每一行名字和数值的宽度
+const MEMINFO_WIDTH: usize = 24;
+
+/// 内存的统计信息,单位是KB,字段参考/proc/meminfo
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemInfo {
+    /// 可以分配的物理内存,不包括内核镜像和保留的内存
+    pub total: usize,
+    /// 空闲的物理内存
+    pub free: usize,
+    /// 高端内存
+    pub high_total: usize,
+    /// 空闲的高端内存
+    pub high_free: usize,
+    /// 内核堆已经映射的内存
+    pub heap: usize,
+    /// 内核堆已经分配出去的内存
+    pub heap_used: usize,
+    /// 内核堆的上限
+    pub heap_ceiling: usize,
+    /// slab分配器占用的内存
+    pub slab: usize,
+    /// 页目录和页表占用的内存
+    pub page_tables: usize,
+    /// 内核栈占用的内存
+    pub kernel_stacks: usize,
+    /// 共享内存段占用的内存
+    pub shm: usize,
+    /// 任务的用户页,用已经分配的内存减去上面各项得到
+    pub tasks: usize,
+    /// 交换区的大小
+    pub swap_total: usize,
+    /// 空闲的交换区
+    pub swap_free: usize,
+}
+
+/// 页数转换成KB
+fn pages_to_kb(pages: usize) -> usize {
+    pages * (BASE_PAGE_SIZE / 1024)
+}
+
+/// 收集当前的内存统计信息
+pub fn mem_info() -> MemInfo {
+    let frames = frame_stats();
+    let heap = heap_size();
+    let slab: usize = slab_stats().iter().map(|stats| stats.pages).sum();
+    let page_tables = page_table_pages();
+    let kernel_stacks = kernel_stack_count() * KERNEL_STACK_PAGES;
+    let shm = shm_pages();
+    let (swap_total, swap_free) = swap_stats();
+
+    // 内核堆的大小按页对齐,向上取整
+    let heap_pages = (heap + BASE_PAGE_SIZE - 1) / BASE_PAGE_SIZE;
+    let tasks = (frames.total - frames.free)
+        .saturating_sub(heap_pages + slab + page_tables + kernel_stacks + shm);
+
+    MemInfo {
+        total: pages_to_kb(frames.total),
+        free: pages_to_kb(frames.free),
+        high_total: pages_to_kb(frames.high_total),
+        high_free: pages_to_kb(frames.high_free),
+        heap: heap / 1024,
+        heap_used: heap_used() / 1024,
+        heap_ceiling: heap_ceiling() / 1024,
+        slab: pages_to_kb(slab),
+        page_tables: pages_to_kb(page_tables),
+        kernel_stacks: pages_to_kb(kernel_stacks),
+        shm: pages_to_kb(shm),
+        tasks: pages_to_kb(tasks),
+        swap_total: pages_to_kb(swap_total),
+        swap_free: pages_to_kb(swap_free),
+    }
+}
+
+impl fmt::Display for MemInfo {
+    /// 按/proc/meminfo的格式输出,每行一项
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items = [
+            ("MemTotal", self.total),
+            ("MemFree", self.free),
+            ("HighTotal", self.high_total),
+            ("HighFree", self.high_free),
+            ("KernelHeap", self.heap),
+            ("KernelHeapUsed", self.heap_used),
+            ("KernelHeapLimit", self.heap_ceiling),
+            ("Slab", self.slab),
+            ("PageTables", self.page_tables),
+            ("KernelStack", self.kernel_stacks),
+            ("Shmem", self.shm),
+            ("Tasks", self.tasks),
+            ("SwapTotal", self.swap_total),
+            ("SwapFree", self.swap_free),
+        ];
+
+        // 名字和数值一共占MEMINFO_WIDTH列,数值右对齐
+        for (name, kb) in items {
+            let width = MEMINFO_WIDTH - name.len();
+            writeln!(f, "{}:{:>width$} kB", name, kb, width = width)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,16 @@
 pub mod allocator;
+pub mod buddy;
+#[cfg(feature = "debug_heap")]
+pub mod debug_heap;
 pub mod detected;
+pub mod frame;
+pub mod kernel_stack;
+pub mod meminfo;
+pub mod oom;
 pub mod page;
+pub mod paging;
+pub mod shm;
+pub mod slab;
+pub mod swap;
+pub mod uaccess;
+pub mod vma;
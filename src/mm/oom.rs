@@ -0,0 +1,90 @@
+//! 物理内存耗尽的时候,杀死占用物理页最多的用户任务
+//! 任务可能正在内核态运行,不能马上回收,只做标记,从用户态进入内核的时候再退出
+use core::ptr::{NonNull, Unique};
+
+use crate::kernel::interrupts::without_interrupt;
+use crate::kernel::tasks::task::{Task, TaskState};
+use crate::mm::page::{walk_user_pages, KERNEL_PAGE_DIR};
+use crate::mm::paging::BASE_PAGE_SIZE;
+use crate::printlnk;
+
+/// 任务映射的物理页数量,内核任务没有用户空间
+fn resident_pages(task: &Task) -> usize {
+    if task.pde == KERNEL_PAGE_DIR {
+        return 0;
+    }
+
+    let mut pages = 0;
+    walk_user_pages(task.pde, 0, |_, _| {
+        pages += 1;
+        true
+    });
+    pages
+}
+
+/// 让出CPU,让被杀死的任务有机会退出
+fn yield_to(victim: Option<Unique<Task>>) {
+    without_interrupt(|| unsafe {
+        if let Some(victim) = victim {
+            Task::wake(NonNull::from(victim));
+        }
+        Task::schedule();
+    });
+}
+
+/// 被杀死之后能不能回到用户态退出,阻塞和等待子任务的任务可能永远不会被唤醒
+fn can_exit(task: &Task) -> bool {
+    matches!(
+        task.state,
+        TaskState::TaskRunning | TaskState::TaskReady | TaskState::TaskSleep
+    )
+}
+
+/// 没有物理页的时候调用,杀死占用物理页最多的用户任务,并让出CPU
+/// 已经有被杀死的任务可以退出的时候只让出CPU,阻塞的任务和init不会被选中
+/// 被杀死的是当前任务时不会返回,没有可以杀死的任务返回false
+pub fn out_of_memory() -> bool {
+    let mut victim: Option<(Unique<Task>, usize)> = None;
+    let mut killed = false;
+    Task::for_each_task(|task| {
+        let task_ref = unsafe { task.as_ref() };
+        if !Task::is_killable(NonNull::from(task)) || !can_exit(task_ref) {
+            return;
+        }
+        if task_ref.killed {
+            killed = true;
+            return;
+        }
+
+        let pages = resident_pages(task_ref);
+        if pages > victim.map_or(0, |(_, max)| max) {
+            victim = Some((task, pages));
+        }
+    });
+
+    // 遍历的时候持有任务表的锁,不能在里面让出CPU
+    if killed {
+        yield_to(None);
+        return true;
+    }
+
+    let Some((mut task, pages)) = victim else {
+        return false;
+    };
+
+    unsafe {
+        printlnk!(
+            "[OOM] kill task {} using {}K",
+            task.as_ref().name,
+            pages * BASE_PAGE_SIZE / 1024
+        );
+        task.as_mut().killed = true;
+
+        if task.as_ptr() == Task::current_task().as_ptr() {
+            Task::kill();
+        }
+    }
+
+    yield_to(Some(task));
+    true
+}
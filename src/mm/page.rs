@@ -1,125 +1,758 @@
-use core::mem::size_of;
-use core::slice;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{ptr, slice};
 
+use crate::kernel::interrupts::without_interrupt;
 use crate::mm::allocator::init_heap;
-use crate::mm::detected::HEAP_MEMORY_BASE;
-use x86::bits32::paging::{
-    pd_index, PAddr, PDEntry, PDFlags, PTEntry, PTFlags, VAddr,
-    PAGE_SIZE_ENTRIES,
+use crate::mm::frame::{
+    alloc_frame, alloc_user_frame, frame_ref_count, free_frame,
+    init_high_frames, memory_top, share_frame,
 };
-use x86::controlregs::{cr0, cr0_write, cr3_write, Cr0};
-use x86::tlb::flush;
+use crate::mm::kernel_stack::init_kernel_stacks;
+use crate::mm::paging::{
+    alloc_page_dir, enable_no_execute, frame_of, free_page_dir,
+    init_kernel_page_dir, no_execute, paddr, page_dir_base, pd_index, pt_index,
+    set_recursive, PDEntry, PDFlags, PTEntry, PTFlags, PhysAddr,
+    BASE_PAGE_SIZE, PAGE_DIR_ENTRIES, PAGE_DIR_PAGES, PAGE_DIR_VADDR,
+    PAGE_SIZE_ENTRIES, PAGE_TABLE_VADDR,
+};
+use crate::mm::swap::{reclaim_pages, swap_dup, swap_free, swap_slot};
+use crate::mm::vma::{
+    VmAreas, USER_MEMORY_BASE, USER_STACK_TOP, USER_TEXT_BASE,
+};
+use x86::controlregs::{cr0, cr0_write, cr3, cr3_write, Cr0};
+use x86::tlb::{flush, flush_all};
+
+/// 内核的虚拟地址起始位置,高1G留给内核,物理内存从这里开始直接映射
+pub const KERNEL_BASE: u32 = 0xC0000000;
+
+/// 内核被loader加载的物理地址,也是启动栈的栈顶
+pub const KERNEL_LOAD_ADDRESS: u32 = 0x10000;
 
 /// 0x1000到0x7c00都是可用区域
 /// 内核页目录的位置设置为0x1000 4KB位置
 /// 第一页页表存储到 0x2000 8KB的位置
 /// 第三页页表存储到 0x3000 12KB的位置
 /// 0x1000 是前期loader的位置,加载完之后,内存就可以另作他用了,嘿嘿
+/// PAE模式下这里是内核的页目录指针表,页目录从页帧分配器分配
 pub const KERNEL_PAGE_DIR: u32 = 0x1000;
 
+/// 启动页目录的位置,入口代码用它开启分页并跳转到高地址
+/// 不能使用0x1000,loader探测的ARDS还在那里
+#[cfg(not(feature = "pae"))]
+pub const BOOT_PAGE_DIR: u32 = 0x4000;
+
+/// 启动页目录指针表的位置,入口代码把它写入cr3
+#[cfg(feature = "pae")]
+pub const BOOT_PAGE_DIR: u32 = 0x7000;
+
+/// PAE的启动页目录,低端地址和内核地址共用
+#[cfg(feature = "pae")]
+pub const BOOT_PAE_PAGE_DIR: u32 = 0x6000;
+
 /// 内核页表索引
-const KERNEL_PAGE_TABLE: KernelPageTableType = [0x2000, 0x3000];
+#[cfg(not(feature = "pae"))]
+pub const KERNEL_PAGE_TABLE: [u32; 2] = [0x2000, 0x3000];
 
-/// 内核页目录索引的类型
-type KernelPageTableType = [u32; 2];
+/// 内核页表索引,PAE的页表只能映射2M
+#[cfg(feature = "pae")]
+pub const KERNEL_PAGE_TABLE: [u32; 4] = [0x2000, 0x3000, 0x4000, 0x5000];
 
-/// 内核的内存空间,内核的页表数*1M,一个页表可以映射1M内存
+/// 启动时映射的内存,内核页表共映射8M
 pub const KERNEL_MEMORY_SIZE: usize =
-    size_of::<KernelPageTableType>() * 0x100000;
+    KERNEL_PAGE_TABLE.len() * PAGE_SIZE_ENTRIES * BASE_PAGE_SIZE;
 
+/// 内核直接映射的物理内存上限,映射到[KERNEL_BASE, KERNEL_BASE + 0x30000000)
+/// 超出的物理内存作为高端内存,只分配给用户的页,通过临时映射访问
+pub const KERNEL_DIRECT_MAP_SIZE: u32 = 0x30000000;
+
+/// 页目录和页表占用的物理页数量,不包括启动时固定的页表
+static PAGE_TABLE_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    /// 链接脚本中用户代码段在内核中的起始位置
+    static suser: u8;
+    /// 链接脚本中用户代码段在内核中的结束位置
+    static euser: u8;
+    /// 链接脚本中内核的起始位置
+    static skernel: u8;
+    /// 链接脚本中内核的结束位置
+    static ekernel: u8;
+}
+
+/// 临时映射的页数,拷贝的时候源和目的各占一页
+const KMAP_PAGES: usize = 2;
+/// 临时映射区域,用来访问直接映射之外的物理页,在递归映射的页表之下
+pub const KMAP_BASE: u32 =
+    PAGE_TABLE_VADDR as u32 - (KMAP_PAGES * BASE_PAGE_SIZE) as u32;
+
+/// 入口代码已经用启动页目录开启了分页,低端8M同时映射在0和KERNEL_BASE
+/// 这里建立正式的内核页目录,只在KERNEL_BASE之上映射物理内存
 #[no_mangle]
 pub fn init_mem_mapping() {
+    // 不可执行的属性要在建立映射之前确定
+    enable_no_execute();
+    init_kernel_page_dir();
+
     // 页目录
-    let page_dir_table: &mut [PDEntry] = unsafe {
-        slice::from_raw_parts_mut(
-            KERNEL_PAGE_DIR as *mut PDEntry,
-            PAGE_SIZE_ENTRIES,
-        )
-    };
+    let page_dir_table = page_dir_of(KERNEL_PAGE_DIR);
+
+    // 页目录全部初始化为0,loader的内存已经不再使用
+    page_dir_table.fill(PDEntry(0));
 
-    // 页目录全部初始化为0,避免被别的地方初始化过
-    // 巨坑,内核的GDT位于0x11a3,这个0初始化,会导致抹掉GDT
-    // 所以初始化顺序先初始化了内核GDT
-    page_dir_table.fill(PDEntry::new(PAddr::from(0), PDFlags::empty()));
+    // 内核直接映射物理内存,至少映射8M,最多映射KERNEL_DIRECT_MAP_SIZE
+    let kernel_pages = (memory_top().min(KERNEL_DIRECT_MAP_SIZE as u64)
+        as usize)
+        .idx()
+        .max(KERNEL_MEMORY_SIZE.idx());
+    let table_count =
+        (kernel_pages + PAGE_SIZE_ENTRIES - 1) / PAGE_SIZE_ENTRIES;
+    let kernel_pde_base = pd_index(KERNEL_BASE);
+
+    // 内核镜像之外的内存都是数据,不可执行
+    let kernel_image = unsafe {
+        virt_to_phys(&skernel as *const u8 as usize).idx() as usize
+            ..(virt_to_phys(&ekernel as *const u8 as usize) as usize
+                + BASE_PAGE_SIZE
+                - 1)
+            .idx()
+    };
 
-    let mut index: usize = 0;
     // 开始映射内核的页表
-    KERNEL_PAGE_TABLE.iter().enumerate().for_each(
-        |(kernel_pd_index, page_addr)| {
-            // 通过页地址获取页表
-            let page_entry_table: &mut [PTEntry] = unsafe {
-                slice::from_raw_parts_mut(
-                    *page_addr as *mut PTEntry,
-                    PAGE_SIZE_ENTRIES,
-                )
-            };
-
-            page_dir_table[kernel_pd_index] = PDEntry::new(
-                PAddr::from((*page_addr).idx_mask()),
-                PDFlags::P | PDFlags::RW | PDFlags::US,
-            );
+    (0..table_count).for_each(|kernel_pd_index| {
+        // 前两个页表的位置是固定的,其余的页表从页帧分配器中分配
+        // 页帧分配器从低地址开始分配,页表在启动时映射的8M之内
+        let page_addr = KERNEL_PAGE_TABLE
+            .get(kernel_pd_index)
+            .copied()
+            .unwrap_or_else(|| {
+                alloc_page_table().expect("no frame for kernel page table")
+            });
+        assert!(
+            (page_addr as usize) < KERNEL_MEMORY_SIZE,
+            "kernel page table {:#x} not mapped",
+            page_addr
+        );
+
+        // 通过页地址获取页表
+        let page_entry_table: &mut [PTEntry] = unsafe {
+            slice::from_raw_parts_mut(
+                phys_to_virt(page_addr) as *mut PTEntry,
+                PAGE_SIZE_ENTRIES,
+            )
+        };
+
+        // 内核的映射只有特权级可以访问
+        page_dir_table[kernel_pde_base + kernel_pd_index] = PDEntry::new(
+            paddr(page_addr.idx_mask().into()),
+            PDFlags::P | PDFlags::RW,
+        );
 
-            // 跳过页表0的位置,第0页不映射
-            page_entry_table.iter_mut().for_each(|pt_entry| {
-                if index == 0 {
-                    index += 1;
+        page_entry_table.iter_mut().enumerate().for_each(
+            |(pt_index, pt_entry)| {
+                let index = kernel_pd_index * PAGE_SIZE_ENTRIES + pt_index;
+                // 超出物理内存的部分不映射
+                if index >= kernel_pages {
+                    *pt_entry = PTEntry(0);
                     return;
                 }
 
-                *pt_entry = PTEntry::new(
-                    // 用PAddr包裹页索引对应的物理内存的起始位置
-                    PAddr::from(index.page()),
-                    PTFlags::P | PTFlags::RW | PTFlags::US,
-                );
-                index += 1;
-            });
-        },
-    );
-
-    // // 将页表的最后一个初始化成自己,方便在启用分页后修改页表
-    if let Some(last_entry) = page_dir_table.last_mut() {
-        *last_entry = PDEntry::new(
-            PAddr::from(KERNEL_PAGE_DIR.idx_mask()),
-            PDFlags::P | PDFlags::RW | PDFlags::US,
+                let mut flags = PTFlags::P | PTFlags::RW;
+                if !kernel_image.contains(&index) {
+                    flags |= no_execute();
+                }
+                // 用PAddr包裹页索引对应的物理内存的起始位置
+                *pt_entry =
+                    PTEntry::new(paddr(index.page() as PhysAddr), flags);
+            },
         );
-    }
+    });
 
-    // 设置页目录
-    set_cr3(page_dir_table);
-    // 开启分页
-    enable_page();
+    // 将页目录的最后几项指向自己,方便在启用分页后修改页表
+    set_recursive(page_dir_table, page_dir_base(KERNEL_PAGE_DIR));
 
-    unsafe {
-        // 初始化内存分配器,虚拟地址1-8M是内核的
-        init_heap(HEAP_MEMORY_BASE, 0x800000);
-    }
+    // 切换到内核页目录,低端地址不再映射
+    set_cr3(KERNEL_PAGE_DIR);
+    // 内核也不能写只读的页
+    enable_write_protect();
+    // 全部的物理内存都映射了,剩下的空闲页可以交给伙伴分配器
+    init_high_frames();
+
+    // 内核堆映射在直接映射区域之上,用完的时候再增长
+    init_heap();
+    init_kernel_stacks();
+    // 临时映射的页表也要提前创建
+    assert!(
+        create_kernel_page_tables(KMAP_BASE, KMAP_PAGES * BASE_PAGE_SIZE),
+        "no memory for kmap page table"
+    );
 }
 
 /// 开启虚拟内存后,获取页目录
 pub fn get_page_dir_table() -> &'static mut [PDEntry] {
     unsafe {
-        slice::from_raw_parts_mut(0xFFFFF000 as *mut PDEntry, PAGE_SIZE_ENTRIES)
+        slice::from_raw_parts_mut(
+            PAGE_DIR_VADDR as *mut PDEntry,
+            PAGE_DIR_ENTRIES,
+        )
     }
 }
 
+/// 开启虚拟内存后,获取虚拟地址所在的页表,页表必须存在
 pub fn get_page_entry_table(addr: u32) -> &'static mut [PTEntry] {
     unsafe {
         slice::from_raw_parts_mut(
-            (0xffc00000 | pd_index(VAddr(addr))) as *mut PTEntry,
-            1,
+            (PAGE_TABLE_VADDR + pd_index(addr).page()) as *mut PTEntry,
+            PAGE_SIZE_ENTRIES,
+        )
+    }
+}
+
+/// 用户空间的页目录项,其余的页目录项属于内核,被所有的地址空间共享
+fn user_pde_range() -> Range<usize> {
+    pd_index(USER_MEMORY_BASE)..pd_index(USER_STACK_TOP)
+}
+
+/// 是否是用户空间的地址
+pub fn is_user_addr(vaddr: u32) -> bool {
+    user_pde_range().contains(&pd_index(vaddr))
+}
+
+/// 物理地址转换成内核可以访问的虚拟地址,内核直接映射了全部的物理内存
+#[inline(always)]
+pub fn phys_to_virt(addr: u32) -> usize {
+    addr as usize + KERNEL_BASE as usize
+}
+
+/// 内核直接映射区域的虚拟地址转换成物理地址
+#[inline(always)]
+pub fn virt_to_phys(addr: usize) -> u32 {
+    assert!(
+        addr >= KERNEL_BASE as usize,
+        "{:#x} is not kernel addr",
+        addr
+    );
+    (addr - KERNEL_BASE as usize) as u32
+}
+
+/// 访问物理页,直接映射的页使用直接映射的地址
+/// 否则临时映射到第slot个临时页,访问期间关闭中断,不能调度
+pub fn with_frame<R>(
+    frame: PhysAddr,
+    slot: usize,
+    f: impl FnOnce(*mut u8) -> R,
+) -> R {
+    if frame < KERNEL_DIRECT_MAP_SIZE as PhysAddr {
+        return f(phys_to_virt(frame as u32) as *mut u8);
+    }
+
+    let vaddr = KMAP_BASE + slot.page() as u32;
+    without_interrupt(|| {
+        // 临时映射的页表已经提前创建,映射不会失败
+        assert!(map_page(vaddr, frame, PTFlags::RW | no_execute()));
+        let result = f(vaddr as *mut u8);
+        unmap_page(vaddr);
+        result
+    })
+}
+
+/// 物理页拷贝
+pub fn copy_frame(dst: PhysAddr, src: PhysAddr) {
+    with_frame(dst, 0, |dst| {
+        with_frame(src, 1, |src| unsafe {
+            ptr::copy_nonoverlapping(src as *const u8, dst, BASE_PAGE_SIZE);
+        })
+    });
+}
+
+/// 物理页清零
+pub fn zero_frame(addr: PhysAddr) {
+    with_frame(addr, 0, |page| unsafe {
+        ptr::write_bytes(page, 0, BASE_PAGE_SIZE);
+    });
+}
+
+/// 通过写入cr3的地址获取页目录
+fn page_dir_of(pde: u32) -> &'static mut [PDEntry] {
+    unsafe {
+        slice::from_raw_parts_mut(
+            phys_to_virt(page_dir_base(pde)) as *mut PDEntry,
+            PAGE_DIR_ENTRIES,
         )
     }
 }
 
+/// 通过页目录项获取页表,页表总是在直接映射的内存中
+fn page_table_of(entry: PDEntry) -> &'static mut [PTEntry] {
+    unsafe {
+        slice::from_raw_parts_mut(
+            phys_to_virt(frame_of(entry.address()) as u32) as *mut PTEntry,
+            PAGE_SIZE_ENTRIES,
+        )
+    }
+}
+
+/// 创建一个新的页目录,共享内核的页目录项,返回写入cr3的物理地址
+/// 没有物理页返回None
+pub fn create_page_dir() -> Option<u32> {
+    let pde = alloc_page_dir()?;
+    PAGE_TABLE_PAGES.fetch_add(PAGE_DIR_PAGES, Ordering::Relaxed);
+
+    let page_dir_table = page_dir_of(pde);
+    // 内核的页表是共享的,只需要拷贝页目录项
+    page_dir_table.copy_from_slice(page_dir_of(KERNEL_PAGE_DIR));
+    page_dir_table[user_pde_range()].fill(PDEntry(0));
+
+    // 最后几个页目录项指向自己
+    set_recursive(page_dir_table, page_dir_base(pde));
+
+    Some(pde)
+}
+
+/// 释放页目录中用户空间的页表和页,以及页目录自己
+/// 不能释放正在使用的页目录
+pub fn destroy_page_dir(pde: u32) {
+    assert_ne!(pde, KERNEL_PAGE_DIR, "can not destroy kernel page dir");
+    assert_ne!(unsafe { cr3() } as u32, pde, "page dir {:#x} in use", pde);
+
+    page_dir_of(pde)[user_pde_range()]
+        .iter()
+        .filter(|entry| entry.is_present())
+        .for_each(|entry| {
+            page_table_of(*entry).iter().for_each(|pt_entry| {
+                if pt_entry.is_present() {
+                    let frame = frame_of(pt_entry.address());
+                    if !is_user_text(frame) {
+                        free_frame(frame);
+                    }
+                } else if let Some(slot) = swap_slot(*pt_entry) {
+                    swap_free(slot);
+                }
+            });
+
+            free_page_table(frame_of(entry.address()));
+        });
+
+    free_page_dir(pde);
+    PAGE_TABLE_PAGES.fetch_sub(PAGE_DIR_PAGES, Ordering::Relaxed);
+}
+
+/// 分配一个物理页作为页表,页表总是在直接映射的内存中
+fn alloc_page_table() -> Option<u32> {
+    let table = alloc_frame()?;
+    PAGE_TABLE_PAGES.fetch_add(1, Ordering::Relaxed);
+    Some(table)
+}
+
+/// 释放alloc_page_table分配的页表
+fn free_page_table(table: PhysAddr) {
+    free_frame(table);
+    PAGE_TABLE_PAGES.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// 页目录和页表占用的物理页数量
+pub fn page_table_pages() -> usize {
+    PAGE_TABLE_PAGES.load(Ordering::Relaxed)
+}
+
+/// 复制页目录,用户空间的页在父子之间共享,私有的页都改为只读
+/// 写的时候再通过缺页复制,返回新页目录的物理地址
+pub fn fork_page_dir(pde: u32, vm_areas: &VmAreas) -> Option<u32> {
+    let child_pde = create_page_dir()?;
+    let child_page_dir_table = page_dir_of(child_pde);
+
+    for index in user_pde_range() {
+        let entry = page_dir_of(pde)[index];
+        if !entry.is_present() {
+            continue;
+        }
+
+        let Some(table) = alloc_page_table() else {
+            destroy_page_dir(child_pde);
+            return None;
+        };
+
+        let page_entry_table = page_table_of(entry);
+        // 换出的页由父子任务共同引用交换区的槽位
+        page_entry_table
+            .iter()
+            .filter_map(|pt_entry| swap_slot(*pt_entry))
+            .for_each(swap_dup);
+        page_entry_table
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, pt_entry)| pt_entry.is_present())
+            .for_each(|(pt_index, pt_entry)| {
+                let vaddr = (index * PAGE_SIZE_ENTRIES + pt_index).page();
+                let shared = vm_areas
+                    .find(vaddr as u32)
+                    .is_some_and(|area| area.kind.is_shared());
+
+                // 共享映射的页不需要写时复制
+                if !shared {
+                    *pt_entry = PTEntry::new(
+                        pt_entry.address(),
+                        pt_entry.flags() - PTFlags::RW,
+                    );
+                }
+                // 用户代码段是内核镜像中保留的页,不记引用计数
+                let frame = frame_of(pt_entry.address());
+                if !is_user_text(frame) {
+                    share_frame(frame);
+                }
+            });
+
+        let child_entry = PDEntry::new(paddr(table.into()), entry.flags());
+        page_table_of(child_entry).copy_from_slice(page_entry_table);
+        child_page_dir_table[index] = child_entry;
+    }
+
+    // 父任务的页变成了只读,刷新快表
+    if unsafe { cr3() } as u32 == pde {
+        flash_tlb_all();
+    }
+
+    Some(child_pde)
+}
+
+/// 获取当前地址空间中虚拟地址所在的页表,页表不存在的时候创建页表
+/// 内核的页表登记在内核页目录中,其他地址空间缺页的时候再同步
+fn get_or_create_page_table(vaddr: u32) -> Option<&'static mut [PTEntry]> {
+    let index = pd_index(vaddr);
+    let entry = &mut get_page_dir_table()[index];
+
+    if !entry.is_present() {
+        if is_user_addr(vaddr) {
+            let table = alloc_page_table()?;
+            zero_frame(table.into());
+            *entry = PDEntry::new(
+                paddr(table.into()),
+                PDFlags::P | PDFlags::RW | PDFlags::US,
+            );
+        } else {
+            let kernel_entry = &mut page_dir_of(KERNEL_PAGE_DIR)[index];
+            if !kernel_entry.is_present() {
+                let table = alloc_page_table()?;
+                zero_frame(table.into());
+                *kernel_entry =
+                    PDEntry::new(paddr(table.into()), PDFlags::P | PDFlags::RW);
+            }
+            *entry = *kernel_entry;
+        }
+
+        // 刷新页表在递归映射中的地址
+        flash_tlb(PAGE_TABLE_VADDR + index.page());
+    }
+
+    Some(get_page_entry_table(vaddr))
+}
+
+/// 提前创建[vaddr, vaddr + size)的内核页表,之后新建的页目录都会共享这些页表
+/// 没有物理页返回false
+pub fn create_kernel_page_tables(vaddr: u32, size: usize) -> bool {
+    assert!(!is_user_addr(vaddr), "{:#x} is not kernel addr", vaddr);
+
+    (0..size)
+        .step_by(BASE_PAGE_SIZE * PAGE_SIZE_ENTRIES)
+        .all(|offset| get_or_create_page_table(vaddr + offset as u32).is_some())
+}
+
+/// 其他地址空间新建了内核页表,同步到当前地址空间
+/// 内核态缺页时调用,同步成功返回true
+pub fn sync_kernel_pde(vaddr: u32) -> bool {
+    if is_user_addr(vaddr) {
+        return false;
+    }
+
+    let index = pd_index(vaddr);
+    let kernel_entry = page_dir_of(KERNEL_PAGE_DIR)[index];
+    let entry = &mut get_page_dir_table()[index];
+    if entry.is_present() || !kernel_entry.is_present() {
+        return false;
+    }
+
+    *entry = kernel_entry;
+    flash_tlb(PAGE_TABLE_VADDR + index.page());
+    true
+}
+
+/// 在当前地址空间中把虚拟页映射到物理页,需要的时候创建页表
+/// 没有物理页创建页表返回false
+pub fn map_page(vaddr: u32, frame: PhysAddr, flags: PTFlags) -> bool {
+    let Some(table) = get_or_create_page_table(vaddr) else {
+        return false;
+    };
+
+    table[pt_index(vaddr)] =
+        PTEntry::new(paddr(frame.idx_mask()), flags | PTFlags::P);
+    flash_tlb(vaddr as usize);
+    true
+}
+
+/// 取消当前地址空间中虚拟页的映射,返回原来映射的物理页
+/// 物理页由调用者处理,比如释放或者什么都不做(设备内存)
+pub fn unmap_page(vaddr: u32) -> Option<PhysAddr> {
+    if !get_page_dir_table()[pd_index(vaddr)].is_present() {
+        return None;
+    }
+
+    let entry = &mut get_page_entry_table(vaddr)[pt_index(vaddr)];
+    if !entry.is_present() {
+        return None;
+    }
+
+    let frame = frame_of(entry.address());
+    *entry = PTEntry(0);
+    flash_tlb(vaddr as usize);
+    Some(frame)
+}
+
+/// 当前地址空间中虚拟地址对应的物理地址
+pub fn translate(vaddr: u32) -> Option<PhysAddr> {
+    if !get_page_dir_table()[pd_index(vaddr)].is_present() {
+        return None;
+    }
+
+    let entry = get_page_entry_table(vaddr)[pt_index(vaddr)];
+    if !entry.is_present() {
+        return None;
+    }
+
+    Some(frame_of(entry.address()) | PhysAddr::from(vaddr & 0xfff))
+}
+
+/// 当前地址空间中虚拟页的页表项属性,页不存在返回None
+pub fn page_flags(vaddr: u32) -> Option<PTFlags> {
+    if !get_page_dir_table()[pd_index(vaddr)].is_present() {
+        return None;
+    }
+
+    let entry = get_page_entry_table(vaddr)[pt_index(vaddr)];
+    entry.is_present().then(|| entry.flags())
+}
+
+/// 映射[vaddr, vaddr + size)到[paddr, paddr + size),地址必须页对齐
+/// 失败的时候撤销已经建立的映射
+pub fn map_range(
+    vaddr: u32,
+    frame: PhysAddr,
+    size: usize,
+    flags: PTFlags,
+) -> bool {
+    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+        if !map_page(vaddr + offset as u32, frame + offset as PhysAddr, flags) {
+            unmap_range(vaddr, offset);
+            return false;
+        }
+    }
+
+    true
+}
+
+/// 取消[vaddr, vaddr + size)的映射,物理页由调用者处理
+pub fn unmap_range(vaddr: u32, size: usize) {
+    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+        unmap_page(vaddr + offset as u32);
+    }
+}
+
+/// 写时复制,页还被其他地址空间共享的时候复制一份,否则直接恢复写权限
+/// 没有可用的物理页返回false
+pub fn copy_on_write(vaddr: u32) -> bool {
+    let entry = &mut get_page_entry_table(vaddr)[pt_index(vaddr)];
+    assert!(entry.is_present(), "copy on write {:#x} not present", vaddr);
+
+    let frame = frame_of(entry.address());
+    let flags = entry.flags() | PTFlags::RW;
+
+    if frame_ref_count(frame) > 1 {
+        let Some(copy) = alloc_user_page() else {
+            return false;
+        };
+        copy_frame(copy, frame);
+        *entry = PTEntry::new(paddr(copy), flags);
+        // 不再引用原来的页
+        free_frame(frame);
+    } else {
+        *entry = PTEntry::new(paddr(frame), flags);
+    }
+
+    flash_tlb(vaddr as usize);
+    true
+}
+
+/// 取消[vaddr, vaddr + size)的映射,并释放物理页和换出的页占用的槽位
+pub fn free_range(vaddr: u32, size: usize) {
+    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+        let page = vaddr + offset as u32;
+        if let Some(frame) = unmap_page(page) {
+            if !is_user_text(frame) {
+                free_frame(frame);
+            }
+        } else if let Some(slot) = page_swap_slot(page) {
+            get_page_entry_table(page)[pt_index(page)] = PTEntry(0);
+            swap_free(slot);
+        }
+    }
+}
+
+/// 修改当前地址空间中[vaddr, vaddr + size)已经映射的页的属性,并刷新快表
+/// 私有区域中fork之后共享的页保持只读,写的时候再复制
+pub fn protect_range(vaddr: u32, size: usize, flags: PTFlags, shared: bool) {
+    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+        let page = vaddr + offset as u32;
+        if !get_page_dir_table()[pd_index(page)].is_present() {
+            continue;
+        }
+
+        let entry = &mut get_page_entry_table(page)[pt_index(page)];
+        if !entry.is_present() {
+            continue;
+        }
+
+        // 保留访问位和脏位
+        let mut page_flags =
+            flags | PTFlags::P | (entry.flags() & (PTFlags::A | PTFlags::D));
+        if !shared && frame_ref_count(frame_of(entry.address())) > 1 {
+            page_flags -= PTFlags::RW;
+        }
+        *entry = PTEntry::new(entry.address(), page_flags);
+        flash_tlb(page as usize);
+    }
+}
+
+/// 当前地址空间中换出的页所在的交换区槽位,页没有换出返回None
+pub fn page_swap_slot(vaddr: u32) -> Option<usize> {
+    if !get_page_dir_table()[pd_index(vaddr)].is_present() {
+        return None;
+    }
+
+    swap_slot(get_page_entry_table(vaddr)[pt_index(vaddr)])
+}
+
+/// 从start开始遍历页目录pde中用户空间存在的页,f返回false时停止
+/// 返回停止时的虚拟地址,遍历完返回None
+pub fn walk_user_pages(
+    pde: u32,
+    start: u32,
+    mut f: impl FnMut(u32, &mut PTEntry) -> bool,
+) -> Option<u32> {
+    let page_dir_table = page_dir_of(pde);
+    let first_pde = pd_index(start);
+    for (index, entry) in page_dir_table
+        .iter()
+        .enumerate()
+        .take(user_pde_range().end)
+        .skip(first_pde)
+    {
+        if !entry.is_present() {
+            continue;
+        }
+
+        let first = if index == first_pde {
+            pt_index(start)
+        } else {
+            0
+        };
+        let page_entry_table = page_table_of(*entry);
+        for (pt_index, pt_entry) in
+            page_entry_table.iter_mut().enumerate().skip(first)
+        {
+            if !pt_entry.is_present() {
+                continue;
+            }
+
+            let vaddr = (index * PAGE_SIZE_ENTRIES + pt_index).page() as u32;
+            if !f(vaddr, pt_entry) {
+                return Some(vaddr);
+            }
+        }
+    }
+
+    None
+}
+
+/// 分配一个用户页,没有空闲的物理页时先把其他的用户页换出到交换区
+pub fn alloc_user_page() -> Option<PhysAddr> {
+    alloc_user_frame().or_else(|| {
+        reclaim_pages();
+        alloc_user_frame()
+    })
+}
+
+/// 用户代码段的大小
+pub fn user_text_size() -> u32 {
+    unsafe { virt_to_phys(&euser as *const u8 as usize) - user_text_phys() }
+}
+
+/// 用户代码段在内核镜像中的物理地址
+fn user_text_phys() -> u32 {
+    unsafe { virt_to_phys(&suser as *const u8 as usize) }
+}
+
+/// 物理页是不是内核镜像中的用户代码段,这些页是保留的,不能共享和释放
+fn is_user_text(frame: PhysAddr) -> bool {
+    let start = PhysAddr::from(user_text_phys());
+    (start..start + PhysAddr::from(user_text_size())).contains(&frame)
+}
+
+/// 把内核镜像中的用户代码段只读映射到当前地址空间的USER_TEXT_BASE
+pub fn map_user_text() -> bool {
+    map_range(
+        USER_TEXT_BASE,
+        user_text_phys().into(),
+        user_text_size() as usize,
+        PTFlags::US,
+    )
+}
+
+/// 在当前地址空间中映射一个清零的物理页
+/// 用户的页优先使用高端内存,内存不够时换出其他的页,没有可用的物理页返回false
+pub fn map_zeroed_page(vaddr: u32, flags: PTFlags) -> bool {
+    let frame = if is_user_addr(vaddr) {
+        alloc_user_page()
+    } else {
+        alloc_frame().map(PhysAddr::from)
+    };
+    let Some(frame) = frame else {
+        return false;
+    };
+    zero_frame(frame);
+
+    if !map_page(vaddr, frame, flags) {
+        free_frame(frame);
+        return false;
+    }
+
+    true
+}
+
+/// 切换页目录,已经是当前页目录则不切换
+pub fn switch_page_dir(pde: u32) {
+    unsafe {
+        if cr3() as u32 != pde {
+            cr3_write(pde as u64);
+        }
+    }
+}
+
 pub fn flash_tlb(addr: usize) {
     unsafe {
         flush(addr);
     }
 }
 
-pub fn set_cr3(page_dir_table: &mut [PDEntry]) {
+/// 刷新全部的快表
+pub fn flash_tlb_all() {
     unsafe {
-        cr3_write(page_dir_table.as_ptr() as u64);
+        flush_all();
+    }
+}
+
+/// 设置页目录的物理地址
+pub fn set_cr3(pde: u32) {
+    unsafe {
+        cr3_write(pde as u64);
     }
 }
 
@@ -131,6 +764,14 @@ pub fn enable_page() {
     }
 }
 
+// 开启写保护,特权级写只读的页也会缺页
+#[inline(always)]
+pub fn enable_write_protect() {
+    unsafe {
+        cr0_write(cr0() | Cr0::CR0_WRITE_PROTECT);
+    }
+}
+
 // 获取页索引
 pub trait PageIndex {
     // 传入物理地址,返回页索引
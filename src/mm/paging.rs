@@ -0,0 +1,231 @@
+//! 分页模式,默认使用两级页表,开启pae特性之后使用PAE分页
+//! PAE模式下4个页目录在物理内存上连续,看作一个有2048项的页目录
+//! 这样两种模式下页目录和页表的用法完全一样,mm::page不需要区分
+pub use mode::*;
+
+/// 物理地址,PAE模式下可以超过4G,两种模式都使用u64
+pub type PhysAddr = u64;
+
+/// 页目录项的数量,每一项映射一个页表
+pub const PAGE_DIR_ENTRIES: usize = PAGE_DIR_PAGES * PAGE_SIZE_ENTRIES;
+
+/// 递归映射之后,页表的起始虚拟地址,页目录的最后几项指向页目录自己
+pub const PAGE_TABLE_VADDR: usize =
+    0usize.wrapping_sub(PAGE_DIR_ENTRIES * BASE_PAGE_SIZE);
+/// 递归映射之后,页目录的虚拟地址
+pub const PAGE_DIR_VADDR: usize =
+    0usize.wrapping_sub(PAGE_DIR_PAGES * BASE_PAGE_SIZE);
+
+/// 虚拟地址对应的页目录项
+pub fn pd_index(vaddr: u32) -> usize {
+    vaddr as usize / (PAGE_SIZE_ENTRIES * BASE_PAGE_SIZE)
+}
+
+/// 虚拟地址对应的页表项
+pub fn pt_index(vaddr: u32) -> usize {
+    vaddr as usize / BASE_PAGE_SIZE % PAGE_SIZE_ENTRIES
+}
+
+/// 页目录的最后几项指向页目录自己,开启分页后通过固定的虚拟地址修改页表
+pub fn set_recursive(page_dir_table: &mut [PDEntry], dir: u32) {
+    let base = PAGE_DIR_ENTRIES - PAGE_DIR_PAGES;
+    page_dir_table[base..]
+        .iter_mut()
+        .enumerate()
+        .for_each(|(index, entry)| {
+            let table = (dir as usize + index * BASE_PAGE_SIZE) as PhysAddr;
+            *entry = PDEntry::new(paddr(table), PDFlags::P | PDFlags::RW);
+        });
+}
+
+/// 两级页表,页表项是4个字节
+#[cfg(not(feature = "pae"))]
+mod mode {
+    pub use x86::bits32::paging::{
+        PAddr, PDEntry, PDFlags, PTEntry, PTFlags, BASE_PAGE_SIZE,
+        PAGE_SIZE_ENTRIES,
+    };
+
+    use super::PhysAddr;
+    use crate::mm::frame::{alloc_frame, free_frame};
+
+    /// 页目录占用的页数
+    pub const PAGE_DIR_PAGES: usize = 1;
+
+    /// 能够管理的最大物理内存
+    pub const MAX_PHYS_MEMORY: u64 = 0x100000000;
+
+    /// 物理地址转换成页表项中的地址
+    pub fn paddr(addr: PhysAddr) -> PAddr {
+        assert!(addr < MAX_PHYS_MEMORY, "paddr {:#x} out of range", addr);
+        PAddr::from(addr as u32)
+    }
+
+    /// 页表项中的物理地址
+    pub fn frame_of(addr: PAddr) -> PhysAddr {
+        addr.as_u32().into()
+    }
+
+    /// 数据页使用的不可执行属性,两级页表没有NX
+    pub fn no_execute() -> PTFlags {
+        PTFlags::empty()
+    }
+
+    /// 两级页表没有NX
+    pub fn enable_no_execute() {}
+
+    /// 页目录的物理地址,pde是写入cr3的地址
+    pub fn page_dir_base(pde: u32) -> u32 {
+        pde
+    }
+
+    /// 内核页目录固定在KERNEL_PAGE_DIR,不需要分配
+    pub fn init_kernel_page_dir() {}
+
+    /// 分配一个页目录,返回写入cr3的地址
+    pub fn alloc_page_dir() -> Option<u32> {
+        alloc_frame()
+    }
+
+    /// 释放alloc_page_dir分配的页目录
+    pub fn free_page_dir(pde: u32) {
+        free_frame(pde.into());
+    }
+}
+
+/// PAE分页,三级页表,页表项是8个字节,最高位是NX
+#[cfg(feature = "pae")]
+mod mode {
+    use core::alloc::{Allocator, Layout};
+    use core::ptr::NonNull;
+    use core::slice;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    pub use x86::bits64::paging::{
+        PAddr, PDEntry, PDFlags, PTEntry, PTFlags, BASE_PAGE_SIZE,
+        PAGE_SIZE_ENTRIES,
+    };
+    use x86::bits64::paging::{PDPTEntry, PDPTFlags};
+    use x86::cpuid::CpuId;
+    use x86::msr::{rdmsr, wrmsr, IA32_EFER};
+
+    use super::PhysAddr;
+    use crate::mm::buddy::Zone;
+    use crate::mm::frame::{alloc_pages, free_pages};
+    use crate::mm::page::{
+        phys_to_virt, virt_to_phys, KERNEL_MEMORY_SIZE, KERNEL_PAGE_DIR,
+    };
+    use crate::mm::slab::SlabAllocator;
+
+    /// 页目录占用的页数,页目录指针表的每一项对应一页
+    pub const PAGE_DIR_PAGES: usize = 4;
+    /// 页目录在伙伴分配器中的阶
+    const PAGE_DIR_ORDER: usize = 2;
+
+    /// 能够管理的最大物理内存,页帧的引用计数数组要放在启动时映射的内存中
+    pub const MAX_PHYS_MEMORY: u64 = 0x400000000;
+
+    /// EFER中开启NX的位
+    const EFER_NXE: u64 = 1 << 11;
+
+    /// CPU是否支持并开启了NX
+    static NO_EXECUTE: AtomicBool = AtomicBool::new(false);
+
+    /// 物理地址转换成页表项中的地址
+    pub fn paddr(addr: PhysAddr) -> PAddr {
+        PAddr::from(addr)
+    }
+
+    /// 页表项中的物理地址
+    pub fn frame_of(addr: PAddr) -> PhysAddr {
+        addr.as_u64()
+    }
+
+    /// 数据页使用的不可执行属性,CPU不支持NX时为空
+    pub fn no_execute() -> PTFlags {
+        if NO_EXECUTE.load(Ordering::Relaxed) {
+            PTFlags::XD
+        } else {
+            PTFlags::empty()
+        }
+    }
+
+    /// CPU支持的时候开启NX,在建立任何不可执行的映射之前调用
+    pub fn enable_no_execute() {
+        let supported = CpuId::new()
+            .get_extended_processor_and_feature_identifiers()
+            .is_some_and(|features| features.has_execute_disable());
+        if supported {
+            unsafe { wrmsr(IA32_EFER, rdmsr(IA32_EFER) | EFER_NXE) };
+        }
+        NO_EXECUTE.store(supported, Ordering::Relaxed);
+    }
+
+    /// 页目录第一页的物理地址,pde是写入cr3的页目录指针表的地址
+    pub fn page_dir_base(pde: u32) -> u32 {
+        let pdpt = unsafe { *(phys_to_virt(pde) as *const PDPTEntry) };
+        frame_of(pdpt.address()) as u32
+    }
+
+    /// 分配内核的页目录,页目录指针表固定在KERNEL_PAGE_DIR
+    /// 这时只有启动时映射的8M可以访问,页目录必须在这里面
+    pub fn init_kernel_page_dir() {
+        let dir = alloc_pages(PAGE_DIR_ORDER, Zone::Dma)
+            .expect("no frame for kernel page dir");
+        assert!(
+            (dir as usize) < KERNEL_MEMORY_SIZE,
+            "kernel page dir {:#x} not mapped",
+            dir
+        );
+        init_page_dir(dir, KERNEL_PAGE_DIR);
+    }
+
+    /// 分配一个页目录,返回写入cr3的页目录指针表的地址
+    pub fn alloc_page_dir() -> Option<u32> {
+        let dir = alloc_pages(PAGE_DIR_ORDER, Zone::Normal)?;
+        let Ok(pdpt) = SlabAllocator.allocate(pdpt_layout()) else {
+            free_pages(dir, PAGE_DIR_ORDER);
+            return None;
+        };
+
+        let pdpt = virt_to_phys(pdpt.as_ptr() as *mut u8 as usize);
+        Some(init_page_dir(dir, pdpt))
+    }
+
+    /// 释放alloc_page_dir分配的页目录和页目录指针表
+    pub fn free_page_dir(pde: u32) {
+        free_pages(page_dir_base(pde), PAGE_DIR_ORDER);
+        unsafe {
+            SlabAllocator.deallocate(
+                NonNull::new_unchecked(phys_to_virt(pde) as *mut u8),
+                pdpt_layout(),
+            );
+        }
+    }
+
+    /// 页目录指针表的布局,4项,32字节对齐
+    fn pdpt_layout() -> Layout {
+        Layout::new::<[PDPTEntry; PAGE_DIR_PAGES]>()
+            .align_to(32)
+            .unwrap()
+    }
+
+    /// 让页目录指针表指向连续的PAGE_DIR_PAGES个页,返回写入cr3的地址
+    /// 页目录指针表必须32字节对齐
+    fn init_page_dir(dir: u32, pdpt: u32) -> u32 {
+        assert_eq!(pdpt & 0x1f, 0, "pdpt {:#x} not aligned", pdpt);
+        let entries = unsafe {
+            slice::from_raw_parts_mut(
+                phys_to_virt(pdpt) as *mut PDPTEntry,
+                PAGE_DIR_PAGES,
+            )
+        };
+
+        // 页目录指针表项只能设置存在位
+        entries.iter_mut().enumerate().for_each(|(index, entry)| {
+            let page = (dir as usize + index * BASE_PAGE_SIZE) as PhysAddr;
+            *entry = PDPTEntry::new(paddr(page), PDPTFlags::P);
+        });
+        pdpt
+    }
+}
@@ -0,0 +1,228 @@
+use alloc::vec::Vec;
+
+use crate::kernel::sync::mutex::Mutex;
+use crate::kernel::system_call::errno::Errno;
+use crate::mm::frame::{alloc_user_frame, free_frame, share_frame};
+use crate::mm::page::{free_range, map_page, zero_frame};
+use crate::mm::paging::{PTFlags, PhysAddr, BASE_PAGE_SIZE};
+use bitflags::bitflags;
+
+/// 共享内存段的最大数量
+const SHM_SEGMENT_NUMBER: usize = 32;
+/// 单个共享内存段的最大大小
+const SHM_MAX_SIZE: usize = 0x400000;
+
+/// 私有的键,总是创建新的段
+pub const IPC_PRIVATE: usize = 0;
+/// shmctl的命令,标记删除
+pub const IPC_RMID: usize = 0;
+/// shmctl的命令,查询段的信息
+pub const IPC_STAT: usize = 2;
+
+bitflags! {
+    /// shmget和shmat的标志
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct ShmFlags: u32 {
+        /// 键不存在的时候创建
+        const CREAT = 0o1000;
+        /// 和CREAT一起使用,键已经存在的时候失败
+        const EXCL = 0o2000;
+        /// 只读挂载
+        const RDONLY = 0o10000;
+    }
+}
+
+/// IPC_STAT返回给用户的段信息
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ShmInfo {
+    pub key: usize,
+    pub size: usize,
+    /// 挂载的次数
+    pub attached: usize,
+}
+
+/// 共享内存段,段本身持有每个物理页的一个引用
+/// 每次挂载时映射的页再各自持有一个引用
+struct ShmSegment {
+    key: usize,
+    size: usize,
+    frames: Vec<PhysAddr>,
+    attached: usize,
+    /// 已经标记删除,最后一个任务卸载时释放
+    removed: bool,
+}
+
+impl ShmSegment {
+    /// 分配清零的物理页,没有内存返回None
+    fn new(key: usize, size: usize) -> Option<Self> {
+        let count = size / BASE_PAGE_SIZE;
+        let mut frames = Vec::new();
+        frames.try_reserve_exact(count).ok()?;
+
+        for _ in 0..count {
+            let Some(frame) = alloc_user_frame() else {
+                frames.iter().for_each(|frame| free_frame(*frame));
+                return None;
+            };
+            zero_frame(frame);
+            frames.push(frame);
+        }
+
+        Some(ShmSegment {
+            key,
+            size,
+            frames,
+            attached: 0,
+            removed: false,
+        })
+    }
+
+    fn info(&self) -> ShmInfo {
+        ShmInfo {
+            key: self.key,
+            size: self.size,
+            attached: self.attached,
+        }
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        self.frames.iter().for_each(|frame| free_frame(*frame));
+    }
+}
+
+const NO_SEGMENT: Option<ShmSegment> = None;
+
+/// 共享内存段表,下标就是段的编号
+static SHM_SEGMENTS: Mutex<[Option<ShmSegment>; SHM_SEGMENT_NUMBER]> =
+    Mutex::new([NO_SEGMENT; SHM_SEGMENT_NUMBER]);
+
+/// 按键查找或者创建共享内存段,返回段的编号,size必须页对齐
+pub fn shm_get(
+    key: usize,
+    size: usize,
+    flags: ShmFlags,
+) -> Result<usize, Errno> {
+    let mut segments = SHM_SEGMENTS.lock();
+
+    if key != IPC_PRIVATE {
+        let found = segments.iter().position(|segment| {
+            segment
+                .as_ref()
+                .is_some_and(|segment| segment.key == key && !segment.removed)
+        });
+
+        if let Some(id) = found {
+            if flags.contains(ShmFlags::CREAT | ShmFlags::EXCL) {
+                return Err(Errno::EEXIST);
+            }
+            if size > segments[id].as_ref().unwrap().size {
+                return Err(Errno::EINVAL);
+            }
+            return Ok(id);
+        }
+
+        if !flags.contains(ShmFlags::CREAT) {
+            return Err(Errno::ENOENT);
+        }
+    }
+
+    if size == 0 || size > SHM_MAX_SIZE {
+        return Err(Errno::EINVAL);
+    }
+
+    let id = segments
+        .iter()
+        .position(|segment| segment.is_none())
+        .ok_or(Errno::ENOSPC)?;
+    segments[id] = Some(ShmSegment::new(key, size).ok_or(Errno::ENOMEM)?);
+    Ok(id)
+}
+
+/// 共享内存段的大小,段不存在返回None
+pub fn shm_size(id: usize) -> Option<usize> {
+    SHM_SEGMENTS
+        .lock()
+        .get(id)?
+        .as_ref()
+        .map(|segment| segment.size)
+}
+
+/// 把共享内存段映射到当前地址空间的vaddr,地址范围由调用者分配
+pub fn shm_attach(id: usize, vaddr: u32, flags: PTFlags) -> Result<(), Errno> {
+    let mut segments = SHM_SEGMENTS.lock();
+    let segment = segments
+        .get_mut(id)
+        .and_then(|segment| segment.as_mut())
+        .ok_or(Errno::EINVAL)?;
+
+    for (index, frame) in segment.frames.iter().enumerate() {
+        let page = vaddr + (index * BASE_PAGE_SIZE) as u32;
+        if !map_page(page, *frame, flags) {
+            free_range(vaddr, index * BASE_PAGE_SIZE);
+            return Err(Errno::ENOMEM);
+        }
+        share_frame(*frame);
+    }
+
+    segment.attached += 1;
+    Ok(())
+}
+
+/// 挂载次数减一,映射的页由调用者释放
+/// 段已经标记删除并且没有任务挂载时释放段
+pub fn shm_detach(id: usize) {
+    let mut segments = SHM_SEGMENTS.lock();
+    let segment = segments[id].as_mut().expect("detach invalid shm segment");
+    assert_ne!(segment.attached, 0, "shm segment {} not attached", id);
+
+    segment.attached -= 1;
+    if segment.removed && segment.attached == 0 {
+        segments[id] = None;
+    }
+}
+
+/// fork之后子任务继承了挂载,挂载次数加一
+pub fn shm_fork(id: usize) {
+    SHM_SEGMENTS.lock()[id]
+        .as_mut()
+        .expect("fork invalid shm segment")
+        .attached += 1;
+}
+
+/// 标记删除共享内存段,键不能再被查找到,没有任务挂载时立即释放
+pub fn shm_remove(id: usize) -> Result<(), Errno> {
+    let mut segments = SHM_SEGMENTS.lock();
+    let segment = segments
+        .get_mut(id)
+        .and_then(|segment| segment.as_mut())
+        .ok_or(Errno::EINVAL)?;
+
+    segment.removed = true;
+    if segment.attached == 0 {
+        segments[id] = None;
+    }
+    Ok(())
+}
+
+/// 所有共享内存段占用的物理页数量
+pub fn shm_pages() -> usize {
+    SHM_SEGMENTS
+        .lock()
+        .iter()
+        .flatten()
+        .map(|segment| segment.frames.len())
+        .sum()
+}
+
+/// 共享内存段的信息
+pub fn shm_stat(id: usize) -> Result<ShmInfo, Errno> {
+    SHM_SEGMENTS
+        .lock()
+        .get(id)
+        .and_then(|segment| segment.as_ref())
+        .map(ShmSegment::info)
+        .ok_or(Errno::EINVAL)
+}
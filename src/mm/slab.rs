@@ -0,0 +1,164 @@
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::NonNull;
+
+use crate::kernel::sync::mutex::Mutex;
+use crate::mm::frame::alloc_frame;
+use crate::mm::page::phys_to_virt;
+use crate::mm::paging::BASE_PAGE_SIZE;
+
+/// 最小的对象大小,空闲对象要存放下一个空闲对象的指针
+const MIN_OBJECT_SIZE: usize = 8;
+/// 对象缓存的数量,对象大小从8字节到一页,每个缓存是前一个的两倍
+pub const SLAB_CACHE_NUMBER: usize = 10;
+
+/// 对象缓存,按对象大小划分
+static SLAB_CACHES: Mutex<[SlabCache; SLAB_CACHE_NUMBER]> = Mutex::new([
+    SlabCache::new(8),
+    SlabCache::new(16),
+    SlabCache::new(32),
+    SlabCache::new(64),
+    SlabCache::new(128),
+    SlabCache::new(256),
+    SlabCache::new(512),
+    SlabCache::new(1024),
+    SlabCache::new(2048),
+    SlabCache::new(4096),
+]);
+
+/// 空闲对象,串成单链表
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+/// 对象缓存的统计信息
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SlabStats {
+    /// 对象大小
+    pub size: usize,
+    /// 占用的物理页数量
+    pub pages: usize,
+    /// 对象总数
+    pub total: usize,
+    /// 已经分配的对象数量
+    pub used: usize,
+}
+
+/// 同一大小对象的缓存,从页帧分配器分配整页,切分成对象
+struct SlabCache {
+    size: usize,
+    free: Option<NonNull<FreeObject>>,
+    pages: usize,
+    total: usize,
+    used: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    const fn new(size: usize) -> Self {
+        SlabCache {
+            size,
+            free: None,
+            pages: 0,
+            total: 0,
+            used: 0,
+        }
+    }
+
+    /// 分配一页切分成对象,放入空闲链表
+    fn grow(&mut self) -> bool {
+        let Some(frame) = alloc_frame() else {
+            return false;
+        };
+
+        let page = phys_to_virt(frame);
+        let count = BASE_PAGE_SIZE / self.size;
+        // 倒着插入,分配的时候从低地址开始
+        (0..count).rev().for_each(|index| {
+            let object = (page + index * self.size) as *mut FreeObject;
+            unsafe {
+                object.write(FreeObject { next: self.free });
+                self.free = Some(NonNull::new_unchecked(object));
+            }
+        });
+
+        self.pages += 1;
+        self.total += count;
+        true
+    }
+
+    fn alloc(&mut self) -> Option<NonNull<u8>> {
+        if self.free.is_none() && !self.grow() {
+            return None;
+        }
+
+        let object = self.free?;
+        self.free = unsafe { object.as_ref().next };
+        self.used += 1;
+        Some(object.cast())
+    }
+
+    fn free(&mut self, ptr: NonNull<u8>) {
+        assert_ne!(self.used, 0, "free {:p} to empty slab cache", ptr);
+
+        let object = ptr.cast::<FreeObject>();
+        unsafe {
+            object.as_ptr().write(FreeObject { next: self.free });
+        }
+        self.free = Some(object);
+        self.used -= 1;
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            size: self.size,
+            pages: self.pages,
+            total: self.total,
+            used: self.used,
+        }
+    }
+}
+
+/// 布局对应的缓存编号,对象大小超过一页返回None
+fn cache_index(layout: Layout) -> Option<usize> {
+    // 对象按大小对齐,对齐要求大于大小的时候按对齐要求分配
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(MIN_OBJECT_SIZE)
+        .next_power_of_two();
+    if size > BASE_PAGE_SIZE {
+        return None;
+    }
+
+    Some((size.trailing_zeros() - MIN_OBJECT_SIZE.trailing_zeros()) as usize)
+}
+
+/// slab分配器,按对象大小从对应的缓存中分配,最大一页
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SlabAllocator;
+
+unsafe impl Allocator for SlabAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let index = cache_index(layout).ok_or(AllocError)?;
+        let mut caches = SLAB_CACHES.lock();
+        let ptr = caches[index].alloc().ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, caches[index].size))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let index = cache_index(layout).expect("invalid slab layout");
+        SLAB_CACHES.lock()[index].free(ptr);
+    }
+}
+
+/// 所有对象缓存的统计信息
+pub fn slab_stats() -> [SlabStats; SLAB_CACHE_NUMBER] {
+    let caches = SLAB_CACHES.lock();
+    let mut stats = [SlabStats::default(); SLAB_CACHE_NUMBER];
+    stats
+        .iter_mut()
+        .zip(caches.iter())
+        .for_each(|(stat, cache)| *stat = cache.stats());
+    stats
+}
@@ -0,0 +1,286 @@
+//! 交换区,物理内存不够的时候把用户的匿名页换出到硬盘
+//! 交换区是硬盘上固定的一段扇区,每个槽位保存一页
+//! 换出的页的页表项不存在,地址部分保存槽位的编号
+//! 回收使用时钟算法,依次扫描每个任务的页,访问过的页清除访问位再给一次机会
+use core::slice;
+
+use crate::drivers::ide::{ide_read, ide_sectors, ide_write, SECTOR_SIZE};
+use crate::kernel::sync::mutex::Mutex;
+use crate::kernel::tasks::task::{Task, TaskState};
+use crate::mm::frame::{frame_ref_count, free_frame};
+use crate::mm::page::{
+    alloc_user_page, flash_tlb, map_page, page_swap_slot, walk_user_pages,
+    with_frame, PageIndex, KERNEL_PAGE_DIR,
+};
+use crate::mm::paging::{
+    frame_of, paddr, PTEntry, PTFlags, PhysAddr, BASE_PAGE_SIZE,
+};
+use crate::printlnk;
+use x86::controlregs::cr3;
+
+/// 交换区的起始扇区,4M之前是引导程序和内核
+const SWAP_START_SECTOR: u32 = 0x2000;
+/// 交换区的扇区数,12M
+const SWAP_SECTORS: u32 = 0x6000;
+/// 每页占用的扇区数
+const SECTORS_PER_PAGE: u32 = (BASE_PAGE_SIZE / SECTOR_SIZE) as u32;
+/// 交换区的槽位数量
+const SWAP_SLOTS: usize = (SWAP_SECTORS / SECTORS_PER_PAGE) as usize;
+/// 每次回收的页数
+const SWAP_CLUSTER: usize = 16;
+
+/// 交换区
+struct SwapArea {
+    /// 每个槽位被多少个页表项引用,0表示空闲
+    /// 槽位0不使用,和空的页表项区分
+    counts: [u8; SWAP_SLOTS],
+    /// 可以使用的槽位数量,没有交换区的时候为0
+    total: usize,
+    /// 空闲的槽位数量
+    free: usize,
+    /// 下一次查找空闲槽位的位置
+    hint: usize,
+}
+
+impl SwapArea {
+    /// 分配一个槽位,交换区满了返回None
+    fn alloc(&mut self) -> Option<usize> {
+        if self.free == 0 {
+            return None;
+        }
+
+        let hint = self.hint;
+        let slot = (hint..=self.total)
+            .chain(1..hint)
+            .find(|slot| self.counts[*slot] == 0)?;
+        self.counts[slot] = 1;
+        self.free -= 1;
+        self.hint = slot % self.total + 1;
+        Some(slot)
+    }
+
+    /// 槽位的引用计数减一,减到0就释放
+    fn free(&mut self, slot: usize) {
+        let count = &mut self.counts[slot];
+        assert_ne!(*count, 0, "free swap slot {} twice", slot);
+
+        *count -= 1;
+        if *count == 0 {
+            self.free += 1;
+        }
+    }
+}
+
+/// 交换区
+static SWAP_AREA: Mutex<SwapArea> = Mutex::new(SwapArea {
+    counts: [0; SWAP_SLOTS],
+    total: 0,
+    free: 0,
+    hint: 1,
+});
+
+/// 时钟算法的指针,指向正在扫描的任务和任务中的虚拟地址
+struct ClockHand {
+    /// 正在扫描的任务的pid
+    pid: u32,
+    vaddr: u32,
+}
+
+static CLOCK_HAND: Mutex<ClockHand> =
+    Mutex::new(ClockHand { pid: 0, vaddr: 0 });
+
+/// 硬盘足够大的时候启用交换区
+pub fn init_swap() {
+    if ide_sectors() < SWAP_START_SECTOR + SWAP_SECTORS {
+        printlnk!("[SWAP] no swap area on disk");
+        return;
+    }
+
+    let mut area = SWAP_AREA.lock();
+    area.total = SWAP_SLOTS - 1;
+    area.free = area.total;
+    printlnk!("[SWAP] {}K swap area", area.total * BASE_PAGE_SIZE / 1024);
+}
+
+/// 换出的页的页表项,地址部分是槽位的编号
+fn swap_entry(slot: usize) -> PTEntry {
+    PTEntry::new(paddr((slot as PhysAddr).page()), PTFlags::empty())
+}
+
+/// 页表项对应的交换区槽位,不是换出的页返回None
+pub fn swap_slot(entry: PTEntry) -> Option<usize> {
+    if entry.is_present() {
+        return None;
+    }
+
+    let slot = frame_of(entry.address()).idx() as usize;
+    (slot != 0).then_some(slot)
+}
+
+/// 槽位被fork之后的任务共享,引用计数加一
+pub fn swap_dup(slot: usize) {
+    let mut area = SWAP_AREA.lock();
+    assert_ne!(area.counts[slot], 0, "dup free swap slot {}", slot);
+    assert!(
+        area.counts[slot] < u8::MAX,
+        "swap slot {} shared too many",
+        slot
+    );
+    area.counts[slot] += 1;
+}
+
+/// 页表项不再引用槽位
+pub fn swap_free(slot: usize) {
+    SWAP_AREA.lock().free(slot)
+}
+
+/// 交换区的槽位总数和空闲数
+pub fn swap_stats() -> (usize, usize) {
+    let area = SWAP_AREA.lock();
+    (area.total, area.free)
+}
+
+/// 槽位的第一个扇区
+fn slot_sector(slot: usize) -> u32 {
+    SWAP_START_SECTOR + slot as u32 * SECTORS_PER_PAGE
+}
+
+/// 把页目录pde中vaddr映射的页写到槽位中,页表项改成换出的页
+/// 只换出没有共享的页,失败返回false
+fn swap_out(pde: u32, vaddr: u32, entry: &mut PTEntry, slot: usize) -> bool {
+    let frame = frame_of(entry.address());
+    if frame_ref_count(frame) != 1 {
+        return false;
+    }
+
+    let written = with_frame(frame, 0, |page| {
+        let page = unsafe { slice::from_raw_parts(page, BASE_PAGE_SIZE) };
+        ide_write(slot_sector(slot), page)
+    });
+    if !written {
+        return false;
+    }
+
+    *entry = swap_entry(slot);
+    if unsafe { cr3() } as u32 == pde {
+        flash_tlb(vaddr as usize);
+    }
+    free_frame(frame);
+    true
+}
+
+/// 从交换区读回当前地址空间中换出的页,按区域的属性重新映射
+/// 没有内存或者读取失败返回false
+pub fn swap_in(vaddr: u32, flags: PTFlags) -> bool {
+    let Some(frame) = alloc_user_page() else {
+        return false;
+    };
+
+    // 分配的时候可能发生了回收,重新读取槽位
+    let Some(slot) = page_swap_slot(vaddr) else {
+        free_frame(frame);
+        return false;
+    };
+
+    let read = with_frame(frame, 0, |page| {
+        let page = unsafe { slice::from_raw_parts_mut(page, BASE_PAGE_SIZE) };
+        ide_read(slot_sector(slot), page)
+    });
+    if !read || !map_page(vaddr, frame, flags) {
+        free_frame(frame);
+        return false;
+    }
+
+    swap_free(slot);
+    true
+}
+
+/// 从vaddr开始扫描任务的页,最多换出target个页
+/// 返回换出的页数,和扫描停止的位置,任务扫描完时为None
+fn scan_task(task: &Task, vaddr: u32, target: usize) -> (usize, Option<u32>) {
+    let pde = task.pde;
+    if pde == KERNEL_PAGE_DIR || task.state == TaskState::TaskDied {
+        return (0, None);
+    }
+
+    let current = unsafe { cr3() } as u32 == pde;
+    let mut reclaimed = 0;
+    let stop = walk_user_pages(pde, vaddr, |page, entry| {
+        // 只换出私有的匿名页,代码段和共享的页不换出
+        let swappable = task
+            .vm_areas
+            .find(page)
+            .is_some_and(|area| area.kind.is_swappable());
+        if !swappable {
+            return true;
+        }
+
+        // 最近访问过的页,清除访问位,下一圈再看
+        if entry.flags().contains(PTFlags::A) {
+            *entry = PTEntry::new(entry.address(), entry.flags() - PTFlags::A);
+            if current {
+                flash_tlb(page as usize);
+            }
+            return true;
+        }
+
+        let Some(slot) = SWAP_AREA.lock().alloc() else {
+            return false;
+        };
+        if swap_out(pde, page, entry, slot) {
+            reclaimed += 1;
+        } else {
+            swap_free(slot);
+        }
+        reclaimed < target
+    });
+
+    (reclaimed, stop.map(|page| page + BASE_PAGE_SIZE as u32))
+}
+
+/// 内存不够的时候换出SWAP_CLUSTER个用户页,返回换出的页数
+/// 最多扫描两圈,第一圈清除的访问位在第二圈就可以换出
+pub fn reclaim_pages() -> usize {
+    if SWAP_AREA.lock().free == 0 {
+        return 0;
+    }
+
+    let mut hand = CLOCK_HAND.lock();
+    let mut reclaimed = 0;
+    let mut rounds = 0;
+    while reclaimed < SWAP_CLUSTER && rounds < 2 {
+        let Some(task) = Task::next_task(hand.pid) else {
+            // 所有的任务都扫描过了,从头开始
+            hand.pid = 0;
+            hand.vaddr = 0;
+            rounds += 1;
+            continue;
+        };
+        let pid = unsafe { task.as_ref().pid };
+        if pid != hand.pid {
+            hand.pid = pid;
+            hand.vaddr = 0;
+        }
+
+        let (count, stop) = scan_task(
+            unsafe { task.as_ref() },
+            hand.vaddr,
+            SWAP_CLUSTER - reclaimed,
+        );
+        reclaimed += count;
+        match stop {
+            Some(vaddr) => hand.vaddr = vaddr,
+            None => {
+                hand.pid = pid + 1;
+                hand.vaddr = 0;
+            }
+        }
+
+        // 交换区满了
+        if SWAP_AREA.lock().free == 0 {
+            break;
+        }
+    }
+
+    reclaimed
+}
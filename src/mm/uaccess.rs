@@ -0,0 +1,91 @@
+use core::ptr;
+
+use crate::kernel::system_call::errno::Errno;
+use crate::kernel::tasks::task::Task;
+use crate::mm::page::{page_flags, PageIndex};
+use crate::mm::paging::{PTFlags, BASE_PAGE_SIZE};
+use crate::mm::vma::USER_STACK_TOP;
+
+/// 检查[addr, addr + len)是否是当前任务可以访问的用户内存
+/// 范围要在用户空间内,被任务登记的区域覆盖,区域和已经映射的页都要允许用户访问
+/// 写的时候区域还要是可写的,只读的共享页在访问时写时复制
+fn access_ok(addr: usize, len: usize, write: bool) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+    if end > USER_STACK_TOP as usize {
+        return false;
+    }
+
+    let current = Task::current_task();
+    let vm_areas = unsafe { &current.as_ref().vm_areas };
+
+    let mut page = addr.idx_mask();
+    while page < end {
+        let Some(area) = vm_areas.find(page as u32) else {
+            return false;
+        };
+
+        if !area.flags.contains(PTFlags::US)
+            || (write && !area.flags.contains(PTFlags::RW))
+        {
+            return false;
+        }
+
+        if page_flags(page as u32)
+            .is_some_and(|flags| !flags.contains(PTFlags::US))
+        {
+            return false;
+        }
+
+        page += BASE_PAGE_SIZE;
+    }
+
+    true
+}
+
+/// 从用户地址src拷贝dst.len()个字节到内核
+pub fn copy_from_user(dst: &mut [u8], src: usize) -> Result<(), Errno> {
+    if !access_ok(src, dst.len(), false) {
+        return Err(Errno::EFAULT);
+    }
+
+    // 没有映射的页在拷贝的时候由缺页中断处理
+    unsafe {
+        ptr::copy_nonoverlapping(src as *const u8, dst.as_mut_ptr(), dst.len());
+    }
+    Ok(())
+}
+
+/// 从内核拷贝src到用户地址dst
+pub fn copy_to_user(dst: usize, src: &[u8]) -> Result<(), Errno> {
+    if !access_ok(dst, src.len(), true) {
+        return Err(Errno::EFAULT);
+    }
+
+    unsafe {
+        ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, src.len());
+    }
+    Ok(())
+}
+
+/// 从用户地址src拷贝以0结尾的字符串到dst,返回字符串的长度,不包括结尾的0
+/// 和linux的strncpy_from_user一样,dst放不下的时候返回dst.len()
+pub fn strncpy_from_user(dst: &mut [u8], src: usize) -> Result<usize, Errno> {
+    let mut len = 0;
+    while len < dst.len() {
+        let addr = src.checked_add(len).ok_or(Errno::EFAULT)?;
+        // 按页拷贝,字符串后面的页可能没有登记
+        let chunk = (BASE_PAGE_SIZE - (addr & (BASE_PAGE_SIZE - 1)))
+            .min(dst.len() - len);
+        let buffer = &mut dst[len..len + chunk];
+        copy_from_user(buffer, addr)?;
+
+        if let Some(end) = buffer.iter().position(|&byte| byte == 0) {
+            return Ok(len + end);
+        }
+        len += chunk;
+    }
+
+    Ok(len)
+}
@@ -0,0 +1,322 @@
+use crate::mm::paging::{no_execute, PTFlags};
+use bitflags::bitflags;
+
+/// 用户空间的起始位置,用户空间占据低端的3G
+pub const USER_MEMORY_BASE: u32 = 0;
+/// 用户栈的栈顶,也是用户空间的结束位置
+pub const USER_STACK_TOP: u32 = 0xC0000000;
+/// 用户栈的最大大小
+pub const USER_STACK_SIZE: u32 = 0x200000;
+/// 用户代码段的起始位置,需要和链接脚本一致
+pub const USER_TEXT_BASE: u32 = 0x08048000;
+/// 用户堆的起始位置,下面留给程序映像
+pub const USER_HEAP_BASE: u32 = 0x10000000;
+
+/// 每个任务最多的虚拟内存区域数量
+const VM_AREA_NUMBER: usize = 16;
+
+/// 虚拟内存区域的类型
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VmKind {
+    /// 内核镜像中的用户代码段,进入用户态的时候映射,不按需分配
+    Code,
+    Stack,
+    Heap,
+    /// 私有的匿名映射,fork之后写时复制
+    Anonymous,
+    /// 共享的匿名映射,fork之后父子任务共享物理页
+    Shared,
+    /// 挂载的共享内存段,记录段的编号
+    Shm(usize),
+}
+
+impl VmKind {
+    /// fork之后父子任务是否共享物理页,不需要写时复制
+    pub fn is_shared(self) -> bool {
+        matches!(self, VmKind::Shared | VmKind::Shm(_))
+    }
+
+    /// 内存不够的时候能否换出到交换区,只换出私有的匿名页
+    pub fn is_swappable(self) -> bool {
+        matches!(self, VmKind::Stack | VmKind::Heap | VmKind::Anonymous)
+    }
+}
+
+bitflags! {
+    /// mmap的内存保护属性
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct MmapProt: u32 {
+        const READ = 0x1;
+        const WRITE = 0x2;
+        const EXEC = 0x4;
+    }
+
+    /// mmap的映射方式
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct MmapFlags: u32 {
+        const SHARED = 0x01;
+        const PRIVATE = 0x02;
+        const FIXED = 0x10;
+        const ANONYMOUS = 0x20;
+    }
+}
+
+impl MmapProt {
+    /// 转换成页表项属性,没有任何权限的页用户态不能访问
+    pub fn page_flags(self) -> PTFlags {
+        let mut flags = PTFlags::empty();
+        if !self.is_empty() {
+            flags |= PTFlags::US;
+        }
+        if self.contains(MmapProt::WRITE) {
+            flags |= PTFlags::RW;
+        }
+        if !self.contains(MmapProt::EXEC) {
+            flags |= no_execute();
+        }
+        flags
+    }
+}
+
+/// 用户的虚拟内存区域[start, end),缺页时按需映射
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VmArea {
+    pub start: u32,
+    pub end: u32,
+    /// 映射页时使用的页表项属性
+    pub flags: PTFlags,
+    pub kind: VmKind,
+}
+
+impl VmArea {
+    pub const fn new(
+        start: u32,
+        end: u32,
+        flags: PTFlags,
+        kind: VmKind,
+    ) -> Self {
+        VmArea {
+            start,
+            end,
+            flags,
+            kind,
+        }
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// 是否和[start, end)有重叠
+    pub fn overlaps(&self, start: u32, end: u32) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// 任务登记的虚拟内存区域
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VmAreas {
+    areas: [Option<VmArea>; VM_AREA_NUMBER],
+}
+
+impl VmAreas {
+    pub const fn new() -> Self {
+        VmAreas {
+            areas: [None; VM_AREA_NUMBER],
+        }
+    }
+
+    /// 登记一个区域,没有空位返回false
+    pub fn insert(&mut self, area: VmArea) -> bool {
+        match self.areas.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(area);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 查找地址所在的区域
+    pub fn find(&self, addr: u32) -> Option<&VmArea> {
+        self.areas.iter().flatten().find(|area| area.contains(addr))
+    }
+
+    /// 堆所在的区域,堆被mprotect拆分成几段的时候返回最高的一段
+    pub fn heap(&mut self) -> Option<&mut VmArea> {
+        self.areas
+            .iter_mut()
+            .flatten()
+            .filter(|area| area.kind == VmKind::Heap)
+            .max_by_key(|area| area.start)
+    }
+
+    /// 所有登记的区域
+    pub fn iter(&self) -> impl Iterator<Item = &VmArea> {
+        self.areas.iter().flatten()
+    }
+
+    /// 和[start, end)有重叠的区域
+    pub fn overlapped(
+        &self,
+        start: u32,
+        end: u32,
+    ) -> impl Iterator<Item = &VmArea> {
+        self.areas
+            .iter()
+            .flatten()
+            .filter(move |area| area.overlaps(start, end))
+    }
+
+    /// 在[low, high)中从高到低查找一段大小为size的空闲地址
+    pub fn find_free(&self, size: u32, low: u32, high: u32) -> Option<u32> {
+        let mut top = high;
+        while top >= low && top - low >= size {
+            let base = top - size;
+            // 和已有的区域重叠,就从重叠区域的下面继续找
+            match self.overlapped(base, top).map(|area| area.start).min() {
+                Some(start) => top = start,
+                None => return Some(base),
+            }
+        }
+
+        None
+    }
+
+    /// [start, end)是否完全被登记的区域覆盖,中间没有空洞
+    pub fn covers(&self, start: u32, end: u32) -> bool {
+        let mut addr = start;
+        while addr < end {
+            match self.find(addr) {
+                Some(area) => addr = area.end,
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// 在addr处把区域拆分成两个,addr不在区域中间时什么也不做
+    /// 需要拆分但是没有空位返回false
+    fn split_at(&mut self, addr: u32) -> bool {
+        let has_slot = self.areas.iter().any(Option::is_none);
+        let Some(area) = self
+            .areas
+            .iter_mut()
+            .flatten()
+            .find(|area| area.start < addr && addr < area.end)
+        else {
+            return true;
+        };
+        if !has_slot {
+            return false;
+        }
+
+        let split = VmArea::new(addr, area.end, area.flags, area.kind);
+        area.end = addr;
+        self.insert(split)
+    }
+
+    /// 修改[start, end)范围内区域的页表项属性,边界上的区域会被拆分
+    /// 修改之后和相邻的属性相同的区域合并
+    /// 范围内有空洞或者拆分区域没有空位返回false,此时不做任何修改
+    pub fn protect(&mut self, start: u32, end: u32, flags: PTFlags) -> bool {
+        if !self.covers(start, end) {
+            return false;
+        }
+
+        let splits = [start, end]
+            .iter()
+            .filter(|addr| {
+                self.iter()
+                    .any(|area| area.start < **addr && **addr < area.end)
+            })
+            .count();
+        let slots = self.areas.iter().filter(|slot| slot.is_none()).count();
+        if splits > slots {
+            return false;
+        }
+
+        assert!(self.split_at(start) && self.split_at(end));
+        self.areas
+            .iter_mut()
+            .flatten()
+            .filter(|area| area.overlaps(start, end))
+            .for_each(|area| area.flags = flags);
+        self.merge(start, end);
+        true
+    }
+
+    /// 合并相接的位置在[start, end]中、属性和类型都相同的区域
+    fn merge(&mut self, start: u32, end: u32) {
+        loop {
+            let pair = (0..VM_AREA_NUMBER).find_map(|low| {
+                let area = self.areas[low]?;
+                if area.end < start || area.end > end {
+                    return None;
+                }
+
+                (0..VM_AREA_NUMBER)
+                    .find(|&high| {
+                        high != low
+                            && self.areas[high].is_some_and(|next| {
+                                next.start == area.end
+                                    && next.flags == area.flags
+                                    && next.kind == area.kind
+                            })
+                    })
+                    .map(|high| (low, high))
+            });
+            let Some((low, high)) = pair else {
+                return;
+            };
+
+            let next = self.areas[high].take().unwrap();
+            self.areas[low].as_mut().unwrap().end = next.end;
+        }
+    }
+
+    /// 移除[start, end)范围内的区域,部分重叠的区域会被截断或者拆分
+    /// 拆分区域没有空位返回false,此时不做任何修改
+    pub fn remove_range(&mut self, start: u32, end: u32) -> bool {
+        let need_split = self
+            .areas
+            .iter()
+            .flatten()
+            .any(|area| area.start < start && end < area.end);
+        let has_slot = self.areas.iter().any(Option::is_none);
+        if need_split && !has_slot {
+            return false;
+        }
+
+        let mut split = None;
+        for slot in self.areas.iter_mut() {
+            let Some(area) = slot else {
+                continue;
+            };
+
+            if !area.overlaps(start, end) {
+                continue;
+            }
+
+            if start <= area.start && area.end <= end {
+                // 整个区域都被移除
+                *slot = None;
+            } else if area.start < start && end < area.end {
+                // 从中间拆分成两个区域
+                split = Some(VmArea::new(end, area.end, area.flags, area.kind));
+                area.end = start;
+            } else if area.start < start {
+                area.end = start;
+            } else {
+                area.start = end;
+            }
+        }
+
+        if let Some(area) = split {
+            self.insert(area);
+        }
+
+        true
+    }
+}
@@ -0,0 +1,50 @@
+; execve装载的用户程序,逐行输出参数之后退出
+; 系统调用号和内核的SysCall一致
+[bits 32]
+
+SYS_WRITE equ 1
+SYS_EXIT equ 14
+STDOUT equ 1
+
+section .text
+global _start
+_start:
+    mov esi, [esp]      ; argc
+    lea edi, [esp + 4]  ; argv
+
+.next_arg:
+    test esi, esi
+    jz .exit
+
+    ; 计算参数的长度
+    mov ecx, [edi]
+    xor edx, edx
+.strlen:
+    cmp byte [ecx + edx], 0
+    je .write
+    inc edx
+    jmp .strlen
+
+.write:
+    mov eax, SYS_WRITE
+    mov ebx, STDOUT
+    int 0x80
+
+    mov eax, SYS_WRITE
+    mov ebx, STDOUT
+    mov ecx, newline
+    mov edx, 1
+    int 0x80
+
+    add edi, 4
+    dec esi
+    jmp .next_arg
+
+.exit:
+    mov eax, SYS_EXIT
+    xor ebx, ebx
+    int 0x80
+
+section .rodata
+newline:
+    db 10